@@ -1,5 +1,8 @@
+use std::cmp;
 use std::collections::BTreeMap;
 
+use rustc_serialize::{Decodable, Decoder, Encodable, Encoder};
+
 // const PIECES: [Piece; 4] = [
 //     Piece {
 //         begin: Point {x: 0, y: 0},
@@ -32,6 +35,24 @@ pub struct Point {
   pub y: u8
 }
 
+// How a piece is allowed to move. New mechanics get a variant here rather
+// than a list of ids on `Game`.
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq)]
+#[derive(Hash)]
+#[derive(RustcDecodable, RustcEncodable)]
+pub enum PieceKind {
+    // Slides along its length
+    Normal,
+    // Never moves
+    Immovable,
+    // Slides across its length instead of along it
+    Perpendicular,
+    // Slides along both axes, like the blocks in Klotski
+    Free
+}
+
 #[derive(Debug)]
 #[derive(Clone)]
 #[derive(PartialEq)]
@@ -39,25 +60,158 @@ pub struct Point {
 pub struct Piece {
     pub begin: Point,
     pub end: Point,
-    pub horizontal: bool
+    pub horizontal: bool,
+    pub kind: PieceKind
 }
 
 impl Piece {
+  // `begin` and `end` are opposite corners, top left and bottom right, of
+  // a rectangle of any size. Pieces at least as wide as they are tall slide
+  // horizontally. The sizes are compared without subtracting so corners
+  // passed the wrong way round can't underflow.
   pub fn new(begin: Point, end: Point) -> Piece {
-    let horizontal = begin.y == end.y;
+    let horizontal = end.x as u16 + begin.y as u16 >= end.y as u16 + begin.x as u16;
 
     Piece {
       begin: begin,
       end: end,
-      horizontal: horizontal
+      horizontal: horizontal,
+      kind: PieceKind::Normal
+    }
+  }
+
+  pub fn can_move(&self) -> bool {
+    match self.kind {
+      PieceKind::Normal | PieceKind::Perpendicular | PieceKind::Free => true,
+      PieceKind::Immovable => false
     }
   }
+
+  // Which axis the piece slides on, if it moves at all. Free pieces slide
+  // on both, and this is the one along their length.
+  pub fn moves_horizontally(&self) -> bool {
+    match self.kind {
+      PieceKind::Perpendicular => !self.horizontal,
+      PieceKind::Normal | PieceKind::Immovable | PieceKind::Free => self.horizontal
+    }
+  }
+
+  // Every way the piece can slide, right or down before left or up and
+  // horizontal before vertical.
+  pub fn directions(&self) -> &'static [Direction] {
+    const HORIZONTAL: [Direction; 2] = [Direction::Right, Direction::Left];
+    const VERTICAL: [Direction; 2] = [Direction::Down, Direction::Up];
+    const BOTH: [Direction; 4] = [Direction::Right, Direction::Left, Direction::Down, Direction::Up];
+
+    match self.kind {
+      PieceKind::Immovable => &[],
+      PieceKind::Free => &BOTH,
+      PieceKind::Normal | PieceKind::Perpendicular => {
+        if self.moves_horizontally() { &HORIZONTAL } else { &VERTICAL }
+      }
+    }
+  }
+}
+
+// A piece's number on the board: the prisoner is 1, and the rest are
+// numbered the same way as in `Board::matrix`. `Game::pieces` is indexed
+// from 0, so piece `id` lives at `id.index()`.
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq)]
+#[derive(PartialOrd, Ord)]
+#[derive(Hash)]
+pub struct PieceId(pub usize);
+
+impl PieceId {
+    pub fn from_index(index: usize) -> PieceId {
+        PieceId(index + 1)
+    }
+
+    pub fn index(&self) -> usize {
+        self.0 - 1
+    }
+}
+
+// Written as the bare number so moves read the same as the matrix.
+impl Encodable for PieceId {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_usize(self.0)
+    }
 }
 
+impl Decodable for PieceId {
+    fn decode<D: Decoder>(d: &mut D) -> Result<PieceId, D::Error> {
+        d.read_usize().map(PieceId)
+    }
+}
+
+// Which way a piece slides on the board, whatever its shape. Up and left are
+// towards row and column 0.
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq)]
+#[derive(Hash)]
+#[derive(RustcDecodable, RustcEncodable)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right
+}
+
+impl Direction {
+    // The direction the old `direction: bool` meant for a piece sliding on
+    // the given axis, where `true` was right or down.
+    pub fn along(horizontal: bool, forwards: bool) -> Direction {
+        match (horizontal, forwards) {
+            (true, true) => Direction::Right,
+            (true, false) => Direction::Left,
+            (false, true) => Direction::Down,
+            (false, false) => Direction::Up
+        }
+    }
+
+    pub fn is_horizontal(&self) -> bool {
+        *self == Direction::Left || *self == Direction::Right
+    }
+
+    // Right and down, towards higher coordinates.
+    pub fn is_forwards(&self) -> bool {
+        *self == Direction::Right || *self == Direction::Down
+    }
+
+    pub fn opposite(&self) -> Direction {
+        match *self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left
+        }
+    }
+}
+
+// A single call to `Game::slide`, kept around so a solution can be replayed
+// or sent to a client without diffing board snapshots.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[derive(RustcDecodable, RustcEncodable)]
+pub struct Move {
+    pub piece: PieceId,
+    pub direction: Direction,
+    pub steps: u8
+}
+
+// What `Board::matrix` holds for a cell no piece can ever enter.
+pub const WALL: i8 = -1;
+
 #[derive(Debug)]
 #[derive(Clone)]
 #[derive(PartialEq)]
 pub struct Board {
+  // 0 for an empty cell, `WALL` for a wall, and otherwise the id of the
+  // piece covering it.
   pub matrix: Vec<Vec<i8>>,
 }
 
@@ -68,8 +222,6 @@ pub struct Game {
   pub width: usize,
   pub height: usize,
   pub pieces: Vec<Piece>,
-  pub disabled_pieces: Vec<usize>,
-  pub reversed_pieces: Vec<usize>,
   pub exit: bool,
   pub exit_point: Point,
   pub parent: Option<usize>
@@ -97,14 +249,12 @@ impl Game {
             height: height,
             board: Board {matrix: vec![vec![0; width]; height]},
             pieces: Vec::new(),
-            disabled_pieces: Vec::new(),
-            reversed_pieces: Vec::new(),
             exit: exit,
             exit_point: exit_point,
             parent: None
         };
 
-        new_game.add(prisoner, false, false);
+        new_game.add(prisoner);
         new_game
     }
 
@@ -132,15 +282,23 @@ impl Game {
         return Game::array_to_game(matrix, exit, Vec::new(), Vec::new());
     }
 
+    // `disabled_pieces` and `reversed_pieces` list the ids of pieces that are
+    // `PieceKind::Immovable` and `PieceKind::Perpendicular`. Cells holding
+    // `WALL` become walls.
     pub fn array_to_game(array: Vec<Vec<i8>>, exit: bool, disabled_pieces: Vec<usize>, reversed_pieces: Vec<usize>) -> Game {
-        let width = array.len();
-        let height = array[0].len();
+        let width = array[0].len();
+        let height = array.len();
 
         let mut hashmap_of_points: BTreeMap<&i8, Vec<Point>> = BTreeMap::new();
+        let mut walls: Vec<Point> = Vec::new();
 
         for y in 0..height {
             for x in 0..width {
                 if array[y][x] == 0 { continue; }
+                if array[y][x] == WALL {
+                    walls.push(Point {x: x as u8, y: y as u8});
+                    continue;
+                }
 
                 if !hashmap_of_points.contains_key(&array[y][x]) {
                     hashmap_of_points.insert(&array[y][x], Vec::new());
@@ -153,16 +311,17 @@ impl Game {
 
         let mut pieces: Vec<Piece> = Vec::new();
 
-        for (_, array_of_points) in hashmap_of_points.iter_mut() {
-            let begin: Point = array_of_points.remove(0);
-
-            let end: Point;
-            if !array_of_points.is_empty() {
-                let len = array_of_points.len();
-                end = array_of_points.remove(len - 1);
-            } else {
-                end = begin.clone();
-            }
+        for (_, array_of_points) in hashmap_of_points.iter() {
+            // Pieces are rectangles, so cells that don't make one are read
+            // as the smallest rectangle around them
+            let begin = Point {
+                x: array_of_points.iter().map(|point| point.x).min().unwrap(),
+                y: array_of_points.iter().map(|point| point.y).min().unwrap()
+            };
+            let end = Point {
+                x: array_of_points.iter().map(|point| point.x).max().unwrap(),
+                y: array_of_points.iter().map(|point| point.y).max().unwrap()
+            };
 
             pieces.push(Piece::new(
                 begin,
@@ -174,15 +333,21 @@ impl Game {
 
         let mut piece_index = 1;
         while !pieces.is_empty() {
-            game.add(
-                pieces.remove(0),
-                disabled_pieces.iter().position(|&r| r == piece_index + 1).is_some(),
-                reversed_pieces.iter().position(|&r| r == piece_index + 1).is_some()
-            );
+            let mut piece = pieces.remove(0);
+            if disabled_pieces.contains(&(piece_index + 1)) {
+                piece.kind = PieceKind::Immovable;
+            } else if reversed_pieces.contains(&(piece_index + 1)) {
+                piece.kind = PieceKind::Perpendicular;
+            }
+            game.add(piece);
 
             piece_index += 1;
         }
 
+        for wall in walls {
+            game.add_wall(wall);
+        }
+
         game
     }
 
@@ -192,7 +357,107 @@ impl Game {
         }
     }
 
-    pub fn add(&mut self, piece: Piece, disabled: bool, reversed: bool) {
+    // Compact encoding of the piece positions. Piece ids and shapes never
+    // change once a game is built, so two games share a key exactly when
+    // their boards are identical.
+    pub fn key(&self) -> Vec<u8> {
+        let mut key = Vec::with_capacity(self.pieces.len() * 2);
+
+        for piece in &self.pieces {
+            key.push(piece.begin.x);
+            key.push(piece.begin.y);
+        }
+
+        key
+    }
+
+    // The same game with its pieces wherever `key` (from `Game::key`) puts
+    // them.
+    pub fn with_key(&self, key: &[u8]) -> Game {
+        let mut game = self.clone();
+
+        for (piece, position) in game.pieces.iter_mut().zip(key.chunks(2)) {
+            piece.end.x = piece.end.x - piece.begin.x + position[0];
+            piece.end.y = piece.end.y - piece.begin.y + position[1];
+            piece.begin.x = position[0];
+            piece.begin.y = position[1];
+        }
+
+        game.redraw();
+        game
+    }
+
+    // Like `key`, but shared by every board that plays out the same way: the
+    // mirror image across the prisoner's lane when the lane runs down the
+    // middle of the board, and boards where identical pieces have swapped
+    // places.
+    pub fn canonical_key(&self) -> Vec<u8> {
+        let key = self.unlabelled_key(false);
+
+        if self.lane_is_central() && self.walls_are_mirrored() {
+            cmp::min(key, self.unlabelled_key(true))
+        } else {
+            key
+        }
+    }
+
+    // Whether flipping the board across the prisoner's lane leaves the
+    // prisoner and the exit where they were.
+    pub fn lane_is_central(&self) -> bool {
+        let prisoner = &self.pieces[0];
+
+        if prisoner.horizontal {
+            prisoner.begin.y as usize + prisoner.end.y as usize + 1 == self.height
+        } else {
+            prisoner.begin.x as usize + prisoner.end.x as usize + 1 == self.width
+        }
+    }
+
+    // Whether every wall has another wall (or itself) opposite it across the
+    // prisoner's lane.
+    fn walls_are_mirrored(&self) -> bool {
+        self.walls().iter().all(|wall| {
+            let (x, y) = if self.pieces[0].horizontal {
+                (wall.x, self.height as u8 - 1 - wall.y)
+            } else {
+                (self.width as u8 - 1 - wall.x, wall.y)
+            };
+            self.board.matrix[y as usize][x as usize] == WALL
+        })
+    }
+
+    // The prisoner's position followed by every other piece's corners and
+    // flags in sorted order, optionally after mirroring across the lane.
+    fn unlabelled_key(&self, mirrored: bool) -> Vec<u8> {
+        let flip = |begin: &Point, end: &Point| -> (Point, Point) {
+            if !mirrored {
+                (begin.clone(), end.clone())
+            } else if self.pieces[0].horizontal {
+                let top = self.height as u8 - 1;
+                (Point {x: begin.x, y: top - end.y}, Point {x: end.x, y: top - begin.y})
+            } else {
+                let right = self.width as u8 - 1;
+                (Point {x: right - end.x, y: begin.y}, Point {x: right - begin.x, y: end.y})
+            }
+        };
+
+        let mut others: Vec<[u8; 5]> = Vec::with_capacity(self.pieces.len() - 1);
+        for piece in self.pieces.iter().skip(1) {
+            let (begin, end) = flip(&piece.begin, &piece.end);
+            others.push([begin.x, begin.y, end.x, end.y, piece.kind as u8]);
+        }
+        others.sort();
+
+        let (prisoner, _) = flip(&self.pieces[0].begin, &self.pieces[0].end);
+        let mut key = vec![prisoner.x, prisoner.y];
+        for other in &others {
+            key.extend_from_slice(other);
+        }
+
+        key
+    }
+
+    pub fn add(&mut self, piece: Piece) {
         self.pieces.push(piece);
 
         let len = self.pieces.len();
@@ -200,14 +465,6 @@ impl Game {
         // Get reference to the newly added piece
         let piece = &self.pieces[len - 1];
 
-        if disabled {
-            self.disabled_pieces.push(len);
-        }
-        if reversed {
-            self.reversed_pieces.push(len);
-        }
-        // println!("{:?}", self.disabled_pieces);
-
         for x in piece.begin.x..(piece.end.x + 1) {
             for y in piece.begin.y..(piece.end.y + 1) {
                 self.board.matrix[y as usize][x as usize] = len as i8;
@@ -215,6 +472,33 @@ impl Game {
         }
     }
 
+    // Block `cell` for good. Returns false, leaving the board alone, if
+    // something is already there.
+    pub fn add_wall(&mut self, cell: Point) -> bool {
+        let value = &mut self.board.matrix[cell.y as usize][cell.x as usize];
+        if *value != 0 {
+            return false;
+        }
+
+        *value = WALL;
+        true
+    }
+
+    // Every wall, row by row.
+    pub fn walls(&self) -> Vec<Point> {
+        let mut walls: Vec<Point> = Vec::new();
+
+        for (y, row) in self.board.matrix.iter().enumerate() {
+            for (x, &value) in row.iter().enumerate() {
+                if value == WALL {
+                    walls.push(Point {x: x as u8, y: y as u8});
+                }
+            }
+        }
+
+        walls
+    }
+
     pub fn check_can_add(&mut self, piece: Piece) -> bool {
         for x in piece.begin.x..(piece.end.x + 1) {
             for y in piece.begin.y..(piece.end.y + 1) {
@@ -227,33 +511,42 @@ impl Game {
         return true;
     }
 
-    pub fn can_exit(&self) -> bool {
-        let mut cells_that_need_to_be_empty: Vec<Point> = Vec::new();
+    // The cells between the prisoner and the exit, across every row (or
+    // column) the prisoner covers.
+    pub fn exit_lane(&self) -> Vec<Point> {
+        let mut cells: Vec<Point> = Vec::new();
+        let prisoner = &self.pieces[0];
 
-        if self.pieces[0].horizontal {
-            if self.exit {
-                for i in (self.pieces[0].end.x + 1)..self.width as u8 {
-                    cells_that_need_to_be_empty.push(Point {x: i, y: self.pieces[0].begin.y});
-                }
+        if prisoner.horizontal {
+            let columns = if self.exit {
+                (prisoner.end.x + 1)..self.width as u8
             } else {
-                for i in 0..self.pieces[0].begin.x {
-                    cells_that_need_to_be_empty.push(Point {x: i, y: self.pieces[0].begin.y});
+                0..prisoner.begin.x
+            };
+            for x in columns {
+                for y in prisoner.begin.y..(prisoner.end.y + 1) {
+                    cells.push(Point {x: x, y: y});
                 }
             }
         } else {
-        if self.exit {
-            for i in (self.pieces[0].end.y + 1)..self.height as u8 {
-                    cells_that_need_to_be_empty.push(Point {x: self.pieces[0].begin.x, y: i});
-                }
-        } else {
-            for i in 0..self.pieces[0].begin.y {
-                    cells_that_need_to_be_empty.push(Point {x: self.pieces[0].begin.x, y: i});
+            let rows = if self.exit {
+                (prisoner.end.y + 1)..self.height as u8
+            } else {
+                0..prisoner.begin.y
+            };
+            for y in rows {
+                for x in prisoner.begin.x..(prisoner.end.x + 1) {
+                    cells.push(Point {x: x, y: y});
                 }
             }
         }
 
+        cells
+    }
+
+    pub fn can_exit(&self) -> bool {
         // Check that the cells are empty
-        for cell in cells_that_need_to_be_empty {
+        for cell in self.exit_lane() {
             if self.board.matrix[cell.y as usize][cell.x as usize] != 0 {
                 return false;
             }
@@ -262,22 +555,90 @@ impl Game {
         true
     }
 
+    pub fn is_disabled(&self, id: PieceId) -> bool {
+        !self.pieces[id.index()].can_move()
+    }
+
+    pub fn moves_horizontally(&self, id: PieceId) -> bool {
+        self.pieces[id.index()].moves_horizontally()
+    }
+
+    // Whether `other` is the same puzzle, with its pieces maybe somewhere
+    // else: the same board, exit and walls, and pieces of the same shapes
+    // and kinds in the same order.
+    pub fn same_puzzle(&self, other: &Game) -> bool {
+        let shape = |piece: &Piece| (piece.end.x - piece.begin.x, piece.end.y - piece.begin.y, piece.kind);
+
+        self.width == other.width && self.height == other.height && self.exit == other.exit &&
+            self.walls() == other.walls() &&
+            self.pieces.len() == other.pieces.len() &&
+            self.pieces.iter().zip(other.pieces.iter()).all(|(piece, other_piece)| shape(piece) == shape(other_piece))
+    }
+
+    // The ids of every piece of `kind`, in order.
+    pub fn ids_of_kind(&self, kind: PieceKind) -> Vec<usize> {
+        (0..self.pieces.len()).filter(|&i| self.pieces[i].kind == kind).map(|i| i + 1).collect()
+    }
+
+    // Every way piece `id` can slide, in the order `Piece::directions` gives.
+    pub fn directions(&self, id: PieceId) -> &'static [Direction] {
+        self.pieces[id.index()].directions()
+    }
+
+    // Rebuild the matrix after pieces have been repositioned directly. Walls
+    // stay where they are.
+    pub fn redraw(&mut self) {
+        for row in self.board.matrix.iter_mut() {
+            for cell in row.iter_mut() {
+                if *cell != WALL {
+                    *cell = 0;
+                }
+            }
+        }
+
+        for (i, piece) in self.pieces.iter().enumerate() {
+            for x in piece.begin.x..(piece.end.x + 1) {
+                for y in piece.begin.y..(piece.end.y + 1) {
+                    self.board.matrix[y as usize][x as usize] = (i + 1) as i8;
+                }
+            }
+        }
+    }
+
+    pub fn apply(&mut self, piece_move: &Move) -> bool {
+        self.slide(piece_move.piece, piece_move.direction, piece_move.steps)
+    }
+
+    // The original form of `slide`, taking an index into `pieces` and `true`
+    // for right or down along whichever axis the piece moves on. Free pieces
+    // can only be moved along their length this way.
     pub fn move_piece(&mut self, piece_index: i8, direction: bool, steps: u8) -> bool {
-        if self.disabled_pieces.iter().position(|&r| r == (piece_index + 1) as usize).is_some() {
+        if piece_index < 0 || piece_index as usize >= self.pieces.len() {
             return false;
         }
 
-        let piece = &mut self.pieces[piece_index as usize];
+        let id = PieceId::from_index(piece_index as usize);
+        let horizontal = self.moves_horizontally(id);
+        self.slide(id, Direction::along(horizontal, direction), steps)
+    }
 
-        let mut horizontal_movement = piece.horizontal;
-        if self.reversed_pieces.iter().position(|&r| r == (piece_index + 1) as usize).is_some() {
-            horizontal_movement = !horizontal_movement;
+    // Slide piece `id` `steps` cells towards `direction`, which has to be one
+    // of its `directions`. Returns whether it could, which it can't for no
+    // steps at all or for a distance off the board.
+    pub fn slide(&mut self, id: PieceId, direction: Direction, steps: u8) -> bool {
+        if steps == 0 || id.0 == 0 || id.0 > self.pieces.len() || !self.directions(id).contains(&direction) {
+            return false;
         }
 
+        let piece_index = id.index() as i8;
+        let horizontal_movement = direction.is_horizontal();
+        let direction = direction.is_forwards();
+        let piece = &mut self.pieces[piece_index as usize];
+
         let mut cells_that_need_to_be_empty: Vec<Point> = Vec::new();
         if horizontal_movement {
             if direction {
-                if (piece.end.x + steps) >= (self.width as u8) { return false }
+                if piece.end.x as usize + steps as usize >= self.width { return false }
 
                 for x in (piece.end.x + 1)..piece.end.x + steps + 1 {
                     for y in piece.begin.y..piece.end.y + 1 {
@@ -285,8 +646,7 @@ impl Game {
                     }
                 }
             } else {
-                if (piece.begin.x as i8 - steps as i8) < 0 { return false }
-
+                if steps > piece.begin.x { return false }
 
                 for x in (piece.begin.x - steps)..piece.begin.x {
                     for y in piece.begin.y..piece.end.y + 1 {
@@ -296,7 +656,7 @@ impl Game {
             }
         } else {
             if direction {
-                if (piece.end.y + steps) >= (self.height as u8) { return false }
+                if piece.end.y as usize + steps as usize >= self.height { return false }
 
                 for x in piece.begin.x..piece.end.x + 1 {
                     for y in (piece.end.y + 1)..piece.end.y + steps + 1 {
@@ -305,7 +665,7 @@ impl Game {
                 }
             }
             else {
-                if (piece.begin.y as i8 - steps as i8) < 0 { return false; }
+                if steps > piece.begin.y { return false }
 
                 for x in piece.begin.x..piece.end.x + 1 {
                     for y in (piece.begin.y - steps)..piece.begin.y {
@@ -368,7 +728,9 @@ impl Game {
 
 #[cfg(test)]
 mod tests {
-    use super::{Game, Piece, Point};
+    use rustc_serialize::json;
+
+    use super::{Direction, Game, Move, Piece, PieceId, PieceKind, Point, WALL};
 
     #[test]
     fn array_to_game() {
@@ -378,17 +740,17 @@ mod tests {
         );
         let exit = true;
         let mut game = Game::new(6, 6, prisoner, exit);
-        game.add(Piece::new(Point {x: 0, y: 0}, Point {x: 0, y: 2}), false, false);
-        game.add(Piece::new(Point {x: 1, y: 1}, Point {x: 2, y: 1}), false, false);
-        game.add(Piece::new(Point {x: 3, y: 0}, Point {x: 3, y: 1}), false, false);
-        game.add(Piece::new(Point {x: 5, y: 0}, Point {x: 5, y: 1}), false, false);
-        game.add(Piece::new(Point {x: 0, y: 3}, Point {x: 2, y: 3}), false, false);
-        game.add(Piece::new(Point {x: 0, y: 5}, Point {x: 1, y: 5}), false, false);
-        game.add(Piece::new(Point {x: 2, y: 4}, Point {x: 2, y: 5}), false, false);
-        game.add(Piece::new(Point {x: 3, y: 5}, Point {x: 4, y: 5}), false, false);
-        game.add(Piece::new(Point {x: 4, y: 2}, Point {x: 4, y: 4}), false, false);
-        game.add(Piece::new(Point {x: 5, y: 3}, Point {x: 5, y: 4}), false, false);
-        game.add(Piece::new(Point {x: 4, y: 0}, Point {x: 4, y: 0}), false, false);
+        game.add(Piece::new(Point {x: 0, y: 0}, Point {x: 0, y: 2}));
+        game.add(Piece::new(Point {x: 1, y: 1}, Point {x: 2, y: 1}));
+        game.add(Piece::new(Point {x: 3, y: 0}, Point {x: 3, y: 1}));
+        game.add(Piece::new(Point {x: 5, y: 0}, Point {x: 5, y: 1}));
+        game.add(Piece::new(Point {x: 0, y: 3}, Point {x: 2, y: 3}));
+        game.add(Piece::new(Point {x: 0, y: 5}, Point {x: 1, y: 5}));
+        game.add(Piece::new(Point {x: 2, y: 4}, Point {x: 2, y: 5}));
+        game.add(Piece::new(Point {x: 3, y: 5}, Point {x: 4, y: 5}));
+        game.add(Piece::new(Point {x: 4, y: 2}, Point {x: 4, y: 4}));
+        game.add(Piece::new(Point {x: 5, y: 3}, Point {x: 5, y: 4}));
+        game.add(Piece::new(Point {x: 4, y: 0}, Point {x: 4, y: 0}));
 
         let converted_game = Game::array_to_game(vec![
             vec![2, 0, 0, 4, 12, 5],
@@ -423,6 +785,259 @@ mod tests {
             [0, 0, 8, 0, 10, 11],
             [7, 7, 8, 9, 9, 0]]
         );
+
+        // Piece 2's cells run diagonally, so it fills the square around them
+        let skewed = Game::string_to_game("[[1,1,0,0],[0,0,0,2],[0,0,2,0],[0,0,0,0]]", true);
+        assert_eq!(skewed.pieces[1].begin, Point {x: 2, y: 1});
+        assert_eq!(skewed.pieces[1].end, Point {x: 3, y: 2});
+        assert_eq!(skewed.board.matrix, vec![
+            vec![1, 1, 0, 0],
+            vec![0, 0, 2, 2],
+            vec![0, 0, 2, 2],
+            vec![0, 0, 0, 0]
+        ]);
+        assert!(skewed.same_puzzle(&skewed.with_key(&skewed.key())));
+    }
+
+    #[test]
+    fn key_follows_board() {
+        let game = Game::array_to_game(vec![
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 2, 0],
+            vec![0, 0, 1, 1, 2, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0]
+        ], true, Vec::new(), Vec::new());
+
+        let mut moved = game.clone();
+        assert_eq!(moved.move_piece(1, true, 2), true);
+        assert!(moved.key() != game.key());
+
+        assert_eq!(moved.move_piece(1, false, 2), true);
+        assert_eq!(moved.key(), game.key());
+        assert_eq!(moved.board.matrix, game.board.matrix);
+
+        assert_eq!(moved.move_piece(1, true, 3), true);
+        assert_eq!(game.with_key(&moved.key()).board.matrix, moved.board.matrix);
+        assert_eq!(moved.with_key(&game.key()).board.matrix, game.board.matrix);
+    }
+
+    #[test]
+    fn slides_by_piece_and_direction() {
+        let game = Game::array_to_game(vec![
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 2, 0],
+            vec![0, 0, 1, 1, 2, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0]
+        ], true, Vec::new(), Vec::new());
+
+        assert_eq!(game.directions(PieceId(1)), [Direction::Right, Direction::Left]);
+        assert_eq!(game.directions(PieceId(2)), [Direction::Down, Direction::Up]);
+
+        let mut moved = game.clone();
+        assert_eq!(moved.slide(PieceId(2), Direction::Left, 1), false);
+        assert_eq!(moved.slide(PieceId(3), Direction::Down, 1), false);
+        assert_eq!(moved.slide(PieceId(2), Direction::Down, 2), true);
+        assert_eq!(moved.slide(PieceId(2), Direction::Down.opposite(), 2), true);
+        assert_eq!(moved.board.matrix, game.board.matrix);
+
+        assert_eq!(moved.slide(PieceId(2), Direction::Down, 0), false);
+        for &steps in &[128, 200, 255] {
+            assert_eq!(moved.slide(PieceId(1), Direction::Right, steps), false);
+            assert_eq!(moved.slide(PieceId(1), Direction::Left, steps), false);
+            assert_eq!(moved.slide(PieceId(2), Direction::Down, steps), false);
+            assert_eq!(moved.slide(PieceId(2), Direction::Up, steps), false);
+        }
+        assert_eq!(moved.board.matrix, game.board.matrix);
+
+        let piece_move = Move {piece: PieceId(2), direction: Direction::Down, steps: 2};
+        let encoded = json::encode(&piece_move).unwrap();
+        assert_eq!(encoded, r#"{"piece":2,"direction":"Down","steps":2}"#);
+        assert_eq!(json::decode::<Move>(&encoded).unwrap(), piece_move);
+    }
+
+    #[test]
+    fn pieces_can_be_any_rectangle() {
+        let game = Game::array_to_game(vec![
+            vec![0, 0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 2, 0, 0, 0],
+            vec![1, 1, 0, 2, 3, 3, 0],
+            vec![1, 1, 0, 0, 3, 3, 0],
+            vec![4, 4, 4, 4, 4, 4, 0]
+        ], true, Vec::new(), Vec::new());
+
+        assert_eq!((game.width, game.height), (7, 5));
+        assert_eq!(game.pieces[0], Piece::new(Point {x: 0, y: 2}, Point {x: 1, y: 3}));
+        assert_eq!(game.pieces[0].horizontal, true);
+        assert_eq!(game.pieces[1].horizontal, false);
+        assert_eq!(game.pieces[2].horizontal, true);
+        assert_eq!(game.exit_lane().len(), 10);
+        assert_eq!(game.can_exit(), false);
+
+        let mut moved = game.clone();
+        assert_eq!(moved.slide(PieceId(3), Direction::Right, 2), false);
+        assert_eq!(moved.slide(PieceId(3), Direction::Right, 1), true);
+        assert_eq!(moved.slide(PieceId(2), Direction::Up, 1), true);
+        assert_eq!(moved.can_exit(), false);
+        assert_eq!(moved.slide(PieceId(4), Direction::Right, 1), true);
+        assert_eq!(moved.board.matrix[4], vec![0, 4, 4, 4, 4, 4, 4]);
+    }
+
+    #[test]
+    fn kinds_decide_how_pieces_move() {
+        let game = Game::array_to_game(vec![
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 2, 0],
+            vec![0, 0, 1, 1, 2, 0],
+            vec![0, 3, 3, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 4]
+        ], true, vec![4], vec![3]);
+
+        assert_eq!(game.pieces[1].kind, PieceKind::Normal);
+        assert_eq!(game.ids_of_kind(PieceKind::Immovable), vec![4]);
+        assert_eq!(game.ids_of_kind(PieceKind::Perpendicular), vec![3]);
+
+        let mut moved = game.clone();
+        assert_eq!(moved.slide(PieceId(3), Direction::Right, 1), false);
+        assert_eq!(moved.slide(PieceId(3), Direction::Down, 2), true);
+        assert_eq!(moved.slide(PieceId(4), Direction::Up, 1), false);
+        assert_eq!(moved.slide(PieceId(4), Direction::Left, 1), false);
+    }
+
+    #[test]
+    fn walls_block_cells_without_being_pieces() {
+        let game = Game::array_to_game(vec![
+            vec![-1, 0, 0, 0, 0, -1],
+            vec![0, 0, 0, 2, 0, 0],
+            vec![1, 1, 0, 2, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, -1, 0, 0],
+            vec![-1, 0, 0, 0, 0, -1]
+        ], true, Vec::new(), Vec::new());
+
+        assert_eq!(game.pieces.len(), 2);
+        assert_eq!(game.pieces[0], Piece::new(Point {x: 0, y: 2}, Point {x: 1, y: 2}));
+        assert_eq!(game.walls().len(), 5);
+        assert_eq!(game.walls()[2], Point {x: 3, y: 4});
+        assert_eq!(game.clone().check_can_add(Piece::new(Point {x: 3, y: 4}, Point {x: 4, y: 4})), false);
+
+        let mut moved = game.clone();
+        assert_eq!(moved.add_wall(Point {x: 3, y: 1}), false);
+        assert_eq!(moved.slide(PieceId(2), Direction::Down, 2), false);
+        assert_eq!(moved.slide(PieceId(2), Direction::Down, 1), true);
+        assert_eq!(game.with_key(&moved.key()).board.matrix, moved.board.matrix);
+        assert_eq!(moved.board.matrix[4][3], WALL);
+
+        assert_eq!(moved.add_wall(Point {x: 5, y: 2}), true);
+        assert_eq!(moved.slide(PieceId(2), Direction::Up, 1), true);
+        assert_eq!(moved.can_exit(), false);
+    }
+
+    #[test]
+    fn mirror_images_need_mirrored_walls() {
+        let top = vec![
+            vec![0, 0, 0, 0, 0, 2, 0],
+            vec![0, 0, 0, 0, -1, 2, 0],
+            vec![0, 0, 0, 0, 0, 0, 0],
+            vec![1, 1, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0, 0]
+        ];
+        let mut bottom = top.clone();
+        bottom[0][5] = 0;
+        bottom[1][5] = 0;
+        bottom[5][5] = 2;
+        bottom[6][5] = 2;
+
+        let lopsided = (Game::array_to_game(top.clone(), true, Vec::new(), Vec::new()),
+                        Game::array_to_game(bottom.clone(), true, Vec::new(), Vec::new()));
+        assert!(lopsided.0.canonical_key() != lopsided.1.canonical_key());
+
+        let (mut top, mut bottom) = lopsided;
+        top.add_wall(Point {x: 4, y: 5});
+        bottom.add_wall(Point {x: 4, y: 5});
+        assert_eq!(top.canonical_key(), bottom.canonical_key());
+    }
+
+    #[test]
+    fn free_pieces_slide_both_ways() {
+        let mut game = Game::array_to_game(vec![
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 2, 2, 0],
+            vec![1, 1, 0, 2, 2, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0]
+        ], true, Vec::new(), Vec::new());
+        game.pieces[1].kind = PieceKind::Free;
+
+        assert_eq!(game.directions(PieceId(2)), [Direction::Right, Direction::Left, Direction::Down, Direction::Up]);
+        assert_eq!(game.ids_of_kind(PieceKind::Free), vec![2]);
+
+        let mut moved = game.clone();
+        assert_eq!(moved.slide(PieceId(2), Direction::Left, 2), false);
+        assert_eq!(moved.slide(PieceId(2), Direction::Left, 1), true);
+        assert_eq!(moved.slide(PieceId(2), Direction::Down, 2), true);
+        assert_eq!(moved.can_exit(), true);
+        assert_eq!(moved.slide(PieceId(2), Direction::Up, 3), true);
+        assert_eq!(moved.move_piece(1, true, 1), true);
+        assert_eq!(moved.pieces[1].begin, Point {x: 3, y: 0});
+    }
+
+    #[test]
+    fn canonical_key_ignores_mirrors_and_labels() {
+        let game = Game::array_to_game(vec![
+            vec![0, 0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 2, 0, 0],
+            vec![0, 0, 0, 0, 2, 0, 0],
+            vec![0, 1, 1, 0, 2, 0, 0],
+            vec![0, 0, 0, 0, 0, 3, 3],
+            vec![0, 0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0, 0]
+        ], true, Vec::new(), Vec::new());
+        let mirrored = Game::array_to_game(vec![
+            vec![0, 0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 2, 2],
+            vec![0, 1, 1, 0, 3, 0, 0],
+            vec![0, 0, 0, 0, 3, 0, 0],
+            vec![0, 0, 0, 0, 3, 0, 0],
+            vec![0, 0, 0, 0, 0, 0, 0]
+        ], true, Vec::new(), Vec::new());
+
+        assert_eq!(game.lane_is_central(), true);
+        assert!(game.key() != mirrored.key());
+        assert_eq!(game.canonical_key(), mirrored.canonical_key());
+
+        // Flipping would move the lane when it isn't in the middle
+        let six = Game::array_to_game(vec![
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 2, 0],
+            vec![0, 0, 1, 1, 2, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0]
+        ], true, Vec::new(), Vec::new());
+        let six_mirrored = Game::array_to_game(vec![
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 1, 1, 0, 0],
+            vec![0, 0, 0, 0, 2, 0],
+            vec![0, 0, 0, 0, 2, 0],
+            vec![0, 0, 0, 0, 0, 0]
+        ], true, Vec::new(), Vec::new());
+
+        assert_eq!(six.lane_is_central(), false);
+        assert!(six.canonical_key() != six_mirrored.canonical_key());
+
+        // A disabled piece doesn't stand in for a movable one
+        let disabled = Game::array_to_game(mirrored.board.matrix.clone(), true, vec![3], Vec::new());
+        assert!(disabled.canonical_key() != game.canonical_key());
     }
 }
 
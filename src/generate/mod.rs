@@ -1,5 +1,7 @@
-use game::{Game, Piece, Point};
-use solver::{solve, extra_solve};
+use std::collections::HashSet;
+
+use game::{Game, Piece, PieceKind, Point};
+use solver::{Metric, Solution, SolveLimits, SolveResult, SolveStats, Solver};
 
 extern crate rand;
 use rand::{thread_rng, sample};
@@ -14,25 +16,43 @@ use rand::{thread_rng, sample};
 // }
 //
 
-pub fn generate(mut initial_game: Game, disabled_bias: i8, reverse_bias: i8) -> Option<Vec<Game>> {
-    let pieces: Vec<Piece> = vec![
-        Piece::new(
-            Point {x: 0, y: 0},
-            Point {x: 1, y: 0}
-        ),
-        Piece::new(
-            Point {x: 0, y: 0},
-            Point {x: 2, y: 0}
-        ),
-        Piece::new(
-            Point {x: 0, y: 0},
-            Point {x: 0, y: 1}
-        ),
-        Piece::new(
-            Point {x: 0, y: 0},
-            Point {x: 0, y: 2}
-        )
-    ];
+// Bars of each of `lengths` lying down, then the same standing up, then a
+// rectangle for each `(width, height)` in `blocks`, all with their top left
+// corner at 0, 0. `palette(&[2, 3], &[])` is the set `generate` has always
+// used. Sizes of zero have no cells and are left out.
+pub fn palette(lengths: &[u8], blocks: &[(u8, u8)]) -> Vec<Piece> {
+    let mut pieces: Vec<Piece> = Vec::new();
+
+    for &length in lengths.iter().filter(|&&length| length > 0) {
+        pieces.push(Piece::new(Point {x: 0, y: 0}, Point {x: length - 1, y: 0}));
+    }
+    for &length in lengths {
+        // A single cell is the same piece either way up
+        if length > 1 {
+            pieces.push(Piece::new(Point {x: 0, y: 0}, Point {x: 0, y: length - 1}));
+        }
+    }
+    for &(width, height) in blocks.iter().filter(|&&(width, height)| width > 0 && height > 0) {
+        pieces.push(Piece::new(Point {x: 0, y: 0}, Point {x: width - 1, y: height - 1}));
+    }
+
+    pieces
+}
+
+// The pieces of a Klotski style puzzle: single cells, dominoes both ways up
+// and two by two blocks, all free to slide along either axis.
+pub fn klotski_palette() -> Vec<Piece> {
+    let mut pieces = palette(&[1, 2], &[(2, 2)]);
+    for piece in pieces.iter_mut() {
+        piece.kind = PieceKind::Free;
+    }
+
+    pieces
+}
+
+// Places pieces from `pieces` at random, keeping each one's kind unless it
+// comes up disabled or, for a normal piece, reversed.
+pub fn generate(mut initial_game: Game, pieces: &[Piece], disabled_bias: i8, reverse_bias: i8, limits: &SolveLimits, metric: Metric, solver: &dyn Solver) -> (SolveResult<Solution>, SolveStats) {
     let mut rng = thread_rng();
 
     for x in 0..initial_game.width {
@@ -50,11 +70,16 @@ pub fn generate(mut initial_game: Game, disabled_bias: i8, reverse_bias: i8) ->
 
             let mut reverse = false;
             let rand_k = sample(&mut rng, 0..100, 1)[0];
-            if rand_k <= reverse_bias && !disabled {
+            if rand_k <= reverse_bias && !disabled && pieces[rand_i as usize].kind == PieceKind::Normal {
                 reverse = true;
             }
 
             let mut piece = pieces[rand_i as usize].clone();
+            if disabled {
+                piece.kind = PieceKind::Immovable;
+            } else if reverse {
+                piece.kind = PieceKind::Perpendicular;
+            }
 
             // Shift dem u guiz
             piece.begin.x += x as u8;
@@ -67,18 +92,180 @@ pub fn generate(mut initial_game: Game, disabled_bias: i8, reverse_bias: i8) ->
             if piece.end.y >= initial_game.height as u8 { continue };
 
             if initial_game.check_can_add(piece.clone()) {
-                initial_game.add(piece, disabled, reverse);
+                initial_game.add(piece);
             }
         }
     }
 
-    let solved_option = solve(&initial_game);
-    match solved_option {
-        Some(_) => {
-            return Some(extra_solve(&initial_game).unwrap());
-        },
-        None => {
-            return None;
+    solver.solve_with_stats(&initial_game, limits, metric)
+}
+
+// Turn each empty cell outside the exit lane into a wall with a
+// `wall_bias` percent chance. Run before `generate` so pieces fill in
+// around the walls.
+pub fn sprinkle_walls(game: &mut Game, wall_bias: i8) {
+    let mut rng = thread_rng();
+    let lane = game.exit_lane();
+
+    for y in 0..game.height as u8 {
+        for x in 0..game.width as u8 {
+            if lane.contains(&Point {x: x, y: y}) {
+                continue;
+            }
+
+            let rand_i = sample(&mut rng, 0..100, 1)[0];
+            if rand_i < wall_bias {
+                game.add_wall(Point {x: x, y: y});
+            }
         }
-    };
+    }
+}
+
+// The puzzles handed out so far, so the same one isn't handed out twice.
+// With `symmetry` a mirror image of an earlier puzzle, or one with identical
+// pieces swapped around, counts as the same puzzle.
+pub struct Dedupe {
+    seen: HashSet<Vec<u8>>,
+    symmetry: bool
+}
+
+impl Dedupe {
+    pub fn new(symmetry: bool) -> Dedupe {
+        Dedupe {seen: HashSet::new(), symmetry: symmetry}
+    }
+
+    // Whether `game` hasn't been seen before, remembering it if so.
+    pub fn insert(&mut self, game: &Game) -> bool {
+        let key = if self.symmetry {
+            // Walls stay put under `canonical_key`'s mirroring, which only
+            // happens when they look the same either way
+            let mut key = game.canonical_key();
+            for wall in game.walls() {
+                key.extend_from_slice(&[wall.x, wall.y]);
+            }
+            key
+        } else {
+            let mut key: Vec<u8> = game.board.matrix.iter().flat_map(|row| row.iter().map(|&cell| cell as u8)).collect();
+            key.extend(game.pieces.iter().map(|piece| piece.kind as u8));
+            key
+        };
+
+        self.seen.insert(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use game::{Game, Piece, PieceKind, Point};
+    use solver::{BreadthFirst, Metric, SolveLimits, SolveResult};
+    use super::{generate, klotski_palette, palette, sprinkle_walls, Dedupe};
+
+    #[test]
+    fn palette_lists_bars_then_blocks() {
+        let old = palette(&[2, 3], &[]);
+        assert_eq!(old.len(), 4);
+        assert_eq!(old[1], Piece::new(Point {x: 0, y: 0}, Point {x: 2, y: 0}));
+        assert_eq!(old[3], Piece::new(Point {x: 0, y: 0}, Point {x: 0, y: 2}));
+
+        let mixed = palette(&[1, 6], &[(2, 2)]);
+        assert_eq!(mixed.len(), 4);
+        assert_eq!(mixed[0], Piece::new(Point {x: 0, y: 0}, Point {x: 0, y: 0}));
+        assert_eq!(mixed[3], Piece::new(Point {x: 0, y: 0}, Point {x: 1, y: 1}));
+
+        assert_eq!(palette(&[0, 2], &[(0, 3), (2, 0)]), palette(&[2], &[]));
+
+        let klotski = klotski_palette();
+        assert_eq!(klotski.len(), 4);
+        assert!(klotski.iter().all(|piece| piece.kind == PieceKind::Free));
+    }
+
+    #[test]
+    fn walls_stay_out_of_the_exit_lane() {
+        let empty = Game::array_to_game(vec![
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![1, 1, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0]
+        ], true, Vec::new(), Vec::new());
+
+        let mut none = empty.clone();
+        sprinkle_walls(&mut none, 0);
+        assert_eq!(none.walls().len(), 0);
+
+        let mut full = empty.clone();
+        sprinkle_walls(&mut full, 100);
+        assert_eq!(full.walls().len(), 30);
+        assert_eq!(full.can_exit(), true);
+        assert_eq!(full.pieces.len(), 1);
+    }
+
+    #[test]
+    fn generates_puzzles_from_any_palette() {
+        let empty = Game::array_to_game(vec![
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![1, 1, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0]
+        ], true, Vec::new(), Vec::new());
+        let pieces = palette(&[1, 4], &[(2, 2), (1, 3)]);
+        let limits = SolveLimits {max_states: Some(10000), ..SolveLimits::default()};
+
+        // Only about one board in eight comes out solvable within the limit
+        let mut solved = 0;
+        for _ in 0..200 {
+            if solved == 5 {
+                break;
+            }
+            if let (SolveResult::Solved(solution), _) = generate(empty.clone(), &pieces, 0, 0, &limits, Metric::Slide, &BreadthFirst::default()) {
+                for piece in solution.games[0].pieces.iter().skip(1) {
+                    let shape = Piece::new(
+                        Point {x: 0, y: 0},
+                        Point {x: piece.end.x - piece.begin.x, y: piece.end.y - piece.begin.y}
+                    );
+                    assert!(pieces.contains(&shape));
+                }
+                solved += 1;
+            }
+        }
+        assert!(solved > 0);
+    }
+
+    #[test]
+    fn dedupe_can_treat_mirror_images_as_the_same_puzzle() {
+        let game = Game::array_to_game(vec![
+            vec![0, 0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 2, 0, 0],
+            vec![0, 0, 0, 0, 2, 0, 0],
+            vec![1, 1, 0, 0, 2, 0, 0],
+            vec![0, 0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0, 0]
+        ], true, Vec::new(), Vec::new());
+        let mirrored = Game::array_to_game(vec![
+            vec![0, 0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0, 0],
+            vec![1, 1, 0, 0, 2, 0, 0],
+            vec![0, 0, 0, 0, 2, 0, 0],
+            vec![0, 0, 0, 0, 2, 0, 0],
+            vec![0, 0, 0, 0, 0, 0, 0]
+        ], true, Vec::new(), Vec::new());
+        let disabled = Game::array_to_game(game.board.matrix.clone(), true, vec![2], Vec::new());
+        let mut walled = game.clone();
+        assert_eq!(walled.add_wall(Point {x: 6, y: 0}), true);
+
+        let mut exact = Dedupe::new(false);
+        let emitted = vec![&game, &game, &mirrored].into_iter().filter(|game| exact.insert(game)).count();
+        assert_eq!(emitted, 2);
+
+        let mut symmetric = Dedupe::new(true);
+        let emitted = vec![&game, &mirrored].into_iter().filter(|game| symmetric.insert(game)).count();
+        assert_eq!(emitted, 1);
+        assert_eq!(symmetric.insert(&disabled), true);
+        assert_eq!(symmetric.insert(&walled), true);
+    }
 }
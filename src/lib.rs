@@ -0,0 +1,6 @@
+extern crate rustc_serialize;
+extern crate rand;
+
+pub mod game;
+pub mod solver;
+pub mod generate;
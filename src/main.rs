@@ -4,26 +4,33 @@ use rustc_serialize::json;
 extern crate rand;
 use rand::{thread_rng, sample};
 
-mod game;
-use game::Game;
-mod solver;
-// use solver::solve;
+extern crate blacksheep;
+use blacksheep::game::Game;
+// use blacksheep::solver::solve;
+use blacksheep::solver::{solver_named, BreadthFirst, Limit, Metric, SolveLimits, SolveResult, SolveStats};
 
-mod generate;
-use generate::generate;
+use blacksheep::generate::{generate, klotski_palette, palette, sprinkle_walls, Dedupe};
 
+use std::cmp;
+use std::collections::HashMap;
 use std::env;
 
-use game::{Piece};
+use blacksheep::game::{Piece, PieceKind, Point, Move};
 
 
 #[derive(RustcDecodable, RustcEncodable)]
 pub struct GameData {
   pub game: Vec<Vec<i8>>,
   pub steps: Vec<Vec<Vec<i8>>>,
+  pub moves: Vec<Move>,
   pub difficulty: i8,
+  pub metric: Metric,
+  pub stats: SolveStats,
   pub disabled_pieces: Vec<usize>,
   pub reversed_pieces: Vec<usize>,
+  // Every piece's kind, the prisoner's first
+  pub kinds: Vec<PieceKind>,
+  pub walls: Vec<Point>,
   pub prisoner: Piece,
   pub width: i8,
   pub height: i8,
@@ -35,6 +42,20 @@ fn main() {
     let minimum_difficulty = env::args().nth(1).unwrap().parse::<i8>().unwrap();
     let disabled_bias = env::args().nth(2).unwrap().parse::<i8>().unwrap();
     let reverse_bias = env::args().nth(3).unwrap().parse::<i8>().unwrap();
+    let metric = match env::args().nth(4) {
+        Some(metric) => metric.parse::<Metric>().unwrap(),
+        None => Metric::Slide
+    };
+    // Fold mirrored boards together while solving
+    let symmetry = match env::args().nth(8).as_deref() {
+        Some("symmetric") => true,
+        Some("exact") | None => false,
+        Some(symmetry) => panic!("unknown symmetry setting: {}", symmetry)
+    };
+    let solver = match env::args().nth(5) {
+        Some(name) => solver_named(&name, symmetry).unwrap(),
+        None => Box::new(BreadthFirst {symmetry: symmetry})
+    };
 
     let games = vec![
         Game::array_to_game(vec![
@@ -138,31 +159,76 @@ fn main() {
             ], true, Vec::new(), Vec::new())
     ];
 
+    // Big enough for any 6x6 or 7x7 puzzle a player would sit through
+    let limits = SolveLimits {max_states: Some(100000), ..SolveLimits::default()};
+
+    let pieces = match env::args().nth(6).as_deref() {
+        Some("klotski") => klotski_palette(),
+        Some("bars") | None => palette(&[2, 3], &[]),
+        Some(family) => panic!("unknown puzzle family: {}", family)
+    };
+    let wall_bias = match env::args().nth(7) {
+        Some(bias) => bias.parse::<i8>().unwrap(),
+        None => 0
+    };
+    // How many different puzzles to print, one JSON object per line
+    let count = match env::args().nth(9) {
+        Some(count) => count.parse::<usize>().unwrap(),
+        None => 1
+    };
+
+    // Why every puzzle thrown away before the ones printed was
+    let mut too_easy = 0;
+    let mut repeats = 0;
+    let mut unsolvable = 0;
+    let mut cut_off: HashMap<Limit, usize> = HashMap::new();
+    let mut dedupe = Dedupe::new(symmetry);
+    let mut printed = 0;
+
     let mut rng = thread_rng();
     let game = games[sample(&mut rng, 0..games.len(), 1)[0] as usize].clone();
     // println!("{:?}", sample(&mut rng, 0..games.len(), 1)[0]);
-    loop {
-        let games_option = generate(game.clone(), disabled_bias, reverse_bias);
+    while printed < count {
+        let mut board = game.clone();
+        sprinkle_walls(&mut board, wall_bias);
+
+        let (games_result, stats) = generate(board, &pieces, disabled_bias, reverse_bias, &limits, metric, &*solver);
         // println!(".");
-        match games_option {
-            Some(games) => {
-                // println!("Found a game of difficulty: {:?}", games.len() as i8);
-                if (games.len() as i8) < minimum_difficulty {
+        match games_result {
+            SolveResult::Solved(solution) => {
+                let cost = solution.cost(metric);
+                // `Metric::Step` solutions can run past what the field holds
+                let difficulty = cmp::min(cost, i8::MAX as usize) as i8;
+                let games = solution.games;
+
+                // println!("Found a game of difficulty: {:?}", difficulty);
+                // The threshold has always counted boards rather than moves
+                if (cost as i64) + 1 < minimum_difficulty as i64 {
+                    too_easy += 1;
                     continue;
                 }
                 // if games[0].disabled_pieces.len() == 0 {
                 //     continue;
                 // }
+                if !dedupe.insert(&games[0]) {
+                    repeats += 1;
+                    continue;
+                }
 
                 let mut game_data = GameData {
                     game: games[0].board.matrix.clone(),
                     steps: Vec::new(),
-                    difficulty: (games.len() as i8) - 1,
+                    moves: solution.moves,
+                    difficulty: difficulty,
+                    metric: metric,
+                    stats: stats,
                     prisoner: game.pieces[0].clone(),
                     width: game.width as i8,
                     height: game.height as i8,
-                    disabled_pieces: games[0].disabled_pieces.clone(),
-                    reversed_pieces: games[0].reversed_pieces.clone()
+                    disabled_pieces: games[0].ids_of_kind(PieceKind::Immovable),
+                    reversed_pieces: games[0].ids_of_kind(PieceKind::Perpendicular),
+                    kinds: games[0].pieces.iter().map(|piece| piece.kind).collect(),
+                    walls: games[0].walls()
                 };
 
                 for i in 1..games.len() {
@@ -170,11 +236,18 @@ fn main() {
                 }
 
                 println!("{}", json::encode(&game_data).unwrap());
-                break;
+                printed += 1;
             },
-            None => {
+            SolveResult::ProvenUnsolvable => {
+                unsolvable += 1;
+                continue;
+            },
+            SolveResult::LimitExceeded(limit) => {
+                *cut_off.entry(limit).or_insert(0) += 1;
                 continue;
             }
         }
     }
+
+    eprintln!("discarded {} too easy, {} repeats, {} unsolvable and {:?} cut off by a limit", too_easy, repeats, unsolvable, cut_off);
 }
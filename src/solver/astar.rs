@@ -0,0 +1,241 @@
+use std::cmp;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::time::Instant;
+
+use game::{Game, Move, PieceId, PieceKind, WALL};
+use super::{exhausted, state_key, successors, unwind, Metric, Node, Solution, SolveLimits, SolveResult, SolveStats, Solver};
+
+// A lower bound on what is left to pay before `game` can exit: every piece
+// standing between the prisoner and the exit has to get out of the way.
+// Returns `None` when one of them never can, because it is a wall, is
+// disabled or only slides along the prisoner's rows (or columns).
+pub fn heuristic(game: &Game, last_move: Option<&Move>, metric: Metric) -> Option<usize> {
+    // A prisoner that can leave its row takes its lane with it, so nothing in
+    // the lane says how far it is from the exit
+    if game.pieces[0].kind != PieceKind::Normal {
+        return Some(0);
+    }
+
+    let mut blockers: Vec<PieceId> = Vec::new();
+
+    for cell in game.exit_lane() {
+        let id = game.board.matrix[cell.y as usize][cell.x as usize];
+        if id == WALL {
+            return None;
+        }
+        if id != 0 && !blockers.contains(&PieceId(id as usize)) {
+            blockers.push(PieceId(id as usize));
+        }
+    }
+
+    let lane_horizontal = game.pieces[0].horizontal;
+    let mut estimate = 0;
+
+    for id in blockers {
+        if !game.directions(id).iter().any(|direction| direction.is_horizontal() != lane_horizontal) {
+            return None;
+        }
+
+        estimate += match metric {
+            Metric::Slide => 1,
+            Metric::Piece => match last_move {
+                // Moving the piece that just moved again is free
+                Some(last_move) if last_move.piece == id => 0,
+                _ => 1
+            },
+            Metric::Step => clearance(game, id)?
+        };
+    }
+
+    Some(estimate)
+}
+
+// The fewest cells blocking piece `id` has to travel sideways to leave the
+// prisoner's lane, or `None` if the board is too small for it to.
+fn clearance(game: &Game, id: PieceId) -> Option<usize> {
+    let prisoner = &game.pieces[0];
+    let piece = &game.pieces[id.index()];
+
+    let (lane_begin, lane_end, piece_begin, piece_end, size) = if prisoner.horizontal {
+        (prisoner.begin.y as usize, prisoner.end.y as usize, piece.begin.y as usize, piece.end.y as usize, game.height)
+    } else {
+        (prisoner.begin.x as usize, prisoner.end.x as usize, piece.begin.x as usize, piece.end.x as usize, game.width)
+    };
+
+    let backwards = piece_end + 1 - lane_begin;
+    let forwards = lane_end + 1 - piece_begin;
+
+    let fits_backwards = backwards <= piece_begin;
+    let fits_forwards = piece_end + forwards < size;
+
+    match (fits_backwards, fits_forwards) {
+        (true, true) => Some(cmp::min(backwards, forwards)),
+        (true, false) => Some(backwards),
+        (false, true) => Some(forwards),
+        (false, false) => None
+    }
+}
+
+// Same contract as `extra_solve`, but states are expanded in order of their
+// cost so far plus `heuristic`, which skips most of the states a plain
+// breadth first search would visit.
+pub fn astar_solve(initial_game: &Game, limits: &SolveLimits, metric: Metric) -> SolveResult<Solution> {
+    astar_solve_with_stats(initial_game, limits, metric, false).0
+}
+
+// `symmetry` as for `solve_with_stats`.
+pub fn astar_solve_with_stats(initial_game: &Game, limits: &SolveLimits, metric: Metric, symmetry: bool) -> (SolveResult<Solution>, SolveStats) {
+    let started = Instant::now();
+    let mut stats = SolveStats::default();
+
+    let result = best_first(initial_game, limits, metric, symmetry, &mut stats);
+    (result, stats.finish(started))
+}
+
+fn best_first(initial_game: &Game, limits: &SolveLimits, metric: Metric, symmetry: bool, stats: &mut SolveStats) -> SolveResult<Solution> {
+    let mut arena: Vec<Node> = Vec::new();
+    let mut best: HashMap<Vec<u8>, usize> = HashMap::new();
+    // Ordered by estimated total, then by estimate left, then by arena index
+    // so ties always break the same way.
+    let mut open: BinaryHeap<Reverse<(usize, usize, usize)>> = BinaryHeap::new();
+    let mut depth_pruned = false;
+
+    let mut root = initial_game.clone();
+    root.parent = None;
+
+    let root_estimate = match heuristic(&root, None, metric) {
+        Some(estimate) => estimate,
+        None => return SolveResult::ProvenUnsolvable
+    };
+
+    best.insert(state_key(&root, None, metric, symmetry), 0);
+    arena.push(Node {game: root, last_move: None, cost: 0});
+    open.push(Reverse((root_estimate, root_estimate, 0)));
+
+    while let Some(Reverse((_, _, index))) = open.pop() {
+        if let Some(limit) = limits.exceeded(best.len()) {
+            return SolveResult::LimitExceeded(limit);
+        }
+
+        let cost = arena[index].cost;
+        stats.reach(cost, open.len() + 1);
+        if best[&state_key(&arena[index].game, arena[index].last_move.as_ref(), metric, symmetry)] < cost {
+            continue;
+        }
+        if arena[index].game.can_exit() {
+            return SolveResult::Solved(unwind(&arena, index, metric));
+        }
+
+        let next_games = successors(&arena[index].game, metric);
+        stats.expand(next_games.len());
+
+        for (piece_move, mut next_game) in next_games {
+            let next_cost = cost + metric.cost(arena[index].last_move.as_ref(), &piece_move);
+            if !limits.allows_depth(next_cost) {
+                depth_pruned = true;
+                continue;
+            }
+
+            let estimate = match heuristic(&next_game, Some(&piece_move), metric) {
+                Some(estimate) => estimate,
+                // Nothing reachable from here can exit
+                None => continue
+            };
+
+            let key = state_key(&next_game, Some(&piece_move), metric, symmetry);
+            let improves = match best.get(&key) {
+                Some(&known) => next_cost < known,
+                None => true
+            };
+
+            if improves {
+                best.insert(key, next_cost);
+                next_game.parent = Some(index);
+                arena.push(Node {game: next_game, last_move: Some(piece_move), cost: next_cost});
+                open.push(Reverse((next_cost + estimate, estimate, arena.len() - 1)));
+            }
+        }
+    }
+
+    exhausted(depth_pruned)
+}
+
+#[derive(Default)]
+pub struct AStar {
+    pub symmetry: bool
+}
+
+impl Solver for AStar {
+    fn solve_with_stats(&self, initial_game: &Game, limits: &SolveLimits, metric: Metric) -> (SolveResult<Solution>, SolveStats) {
+        astar_solve_with_stats(initial_game, limits, metric, self.symmetry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use game::Game;
+    use solver::{extra_solve, Metric, SolveLimits, SolveResult};
+    use solver::fixtures::tricky_game;
+    use super::{astar_solve, heuristic};
+
+    #[test]
+    fn counts_pieces_in_the_way() {
+        let game = Game::array_to_game(vec![
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 2, 0],
+            vec![0, 0, 1, 1, 2, 3],
+            vec![0, 0, 0, 0, 2, 3],
+            vec![0, 0, 0, 0, 0, 3],
+            vec![0, 0, 0, 0, 0, 0]
+        ], true, Vec::new(), Vec::new());
+
+        assert_eq!(heuristic(&game, None, Metric::Slide), Some(2));
+        assert_eq!(heuristic(&game, None, Metric::Step), Some(3));
+
+        let disabled = Game::array_to_game(game.board.matrix.clone(), true, vec![3], Vec::new());
+        assert_eq!(heuristic(&disabled, None, Metric::Slide), None);
+
+        let reversed = Game::array_to_game(game.board.matrix.clone(), true, Vec::new(), vec![2]);
+        assert_eq!(heuristic(&reversed, None, Metric::Slide), None);
+    }
+
+    #[test]
+    fn finds_solutions_as_short_as_breadth_first() {
+        let game = tricky_game();
+
+        for &metric in &[Metric::Slide, Metric::Step, Metric::Piece] {
+            let expected = extra_solve(&game, &SolveLimits::default(), metric).unwrap();
+            let solution = astar_solve(&game, &SolveLimits::default(), metric).unwrap();
+
+            assert_eq!(solution.cost(metric), expected.cost(metric));
+            assert_eq!(solution.games.last().unwrap().can_exit(), true);
+        }
+    }
+
+    #[test]
+    fn proves_blocked_lane_unsolvable() {
+        let game = Game::array_to_game(vec![
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![1, 1, 0, 0, 0, 2],
+            vec![0, 0, 0, 0, 0, 2],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+        ], true, vec![2], Vec::new());
+
+        assert_eq!(astar_solve(&game, &SolveLimits::default(), Metric::Slide).map(|_| ()), SolveResult::ProvenUnsolvable);
+
+        let walled = Game::array_to_game(vec![
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![1, 1, 0, 0, -1, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+        ], true, Vec::new(), Vec::new());
+
+        assert_eq!(heuristic(&walled, None, Metric::Slide), None);
+        assert_eq!(astar_solve(&walled, &SolveLimits::default(), Metric::Slide).map(|_| ()), SolveResult::ProvenUnsolvable);
+    }
+}
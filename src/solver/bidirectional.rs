@@ -0,0 +1,419 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use game::{Game, Move, Piece, PieceId, PieceKind, Point, WALL};
+use super::{extra_solve_with_stats, merge_steps, successors, unwind, Limit, Metric, Node, Solution, SolveLimits, SolveResult, SolveStats, Solver};
+
+// One half of the search: every state it has reached, keyed to their place in
+// `arena`, and the states it reached most recently.
+struct Side {
+    arena: Vec<Node>,
+    index: HashMap<Vec<u8>, usize>,
+    frontier: Vec<usize>,
+    depth: usize
+}
+
+impl Side {
+    fn new(games: Vec<Game>) -> Side {
+        let mut side = Side {arena: Vec::new(), index: HashMap::new(), frontier: Vec::new(), depth: 0};
+
+        for mut game in games {
+            game.parent = None;
+            side.index.insert(game.key(), side.arena.len());
+            side.frontier.push(side.arena.len());
+            side.arena.push(Node {game: game, last_move: None, cost: 0});
+        }
+
+        side
+    }
+}
+
+// Same contract as `extra_solve`. Searches forwards from `initial_game` and
+// backwards from every arrangement the prisoner could leave from, a level at
+// a time on whichever side has the smaller frontier, until the two meet.
+//
+// Every move can be undone, so searching backwards uses the same moves as
+// searching forwards. `Metric::Piece` makes the cost of a move depend on the
+// one before it, which the backward half can't know, so that metric falls
+// back to `extra_solve`. So do games with free pieces, which can end up
+// almost anywhere and leave far too many goal states to list.
+pub fn bidirectional_solve(initial_game: &Game, limits: &SolveLimits, metric: Metric) -> SolveResult<Solution> {
+    bidirectional_solve_with_stats(initial_game, limits, metric).0
+}
+
+pub fn bidirectional_solve_with_stats(initial_game: &Game, limits: &SolveLimits, metric: Metric) -> (SolveResult<Solution>, SolveStats) {
+    if metric == Metric::Piece || initial_game.pieces.iter().any(|piece| piece.kind == PieceKind::Free) {
+        return extra_solve_with_stats(initial_game, limits, metric, false);
+    }
+
+    let started = Instant::now();
+    let mut stats = SolveStats::default();
+
+    let result = meet_in_the_middle(initial_game, limits, metric, &mut stats);
+    (result, stats.finish(started))
+}
+
+fn meet_in_the_middle(initial_game: &Game, limits: &SolveLimits, metric: Metric, stats: &mut SolveStats) -> SolveResult<Solution> {
+    if initial_game.can_exit() {
+        let mut game = initial_game.clone();
+        game.parent = None;
+        return SolveResult::Solved(Solution {games: vec![game], moves: Vec::new()});
+    }
+
+    let goals = match goal_states(initial_game, limits) {
+        Ok(goals) => goals,
+        Err(limit) => return SolveResult::LimitExceeded(limit)
+    };
+
+    let mut forward = Side::new(vec![initial_game.clone()]);
+    let mut backward = Side::new(goals);
+
+    loop {
+        stats.reach(forward.depth + backward.depth, forward.frontier.len() + backward.frontier.len());
+        if forward.frontier.is_empty() || backward.frontier.is_empty() {
+            return SolveResult::ProvenUnsolvable;
+        }
+        if !limits.allows_depth(forward.depth + backward.depth + 1) {
+            return SolveResult::LimitExceeded(Limit::Depth);
+        }
+
+        let meeting = if forward.frontier.len() <= backward.frontier.len() {
+            expand(&mut forward, &backward, limits, metric, stats)
+        } else {
+            expand(&mut backward, &forward, limits, metric, stats).map(|meeting| {
+                meeting.map(|(from_goal, from_start)| (from_start, from_goal))
+            })
+        };
+
+        match meeting {
+            Ok(Some((from_start, from_goal))) => {
+                return SolveResult::Solved(join(&forward, from_start, &backward, from_goal, metric));
+            },
+            Ok(None) => {},
+            Err(limit) => {
+                return SolveResult::LimitExceeded(limit);
+            }
+        }
+    }
+}
+
+pub struct Bidirectional;
+
+impl Solver for Bidirectional {
+    fn solve_with_stats(&self, initial_game: &Game, limits: &SolveLimits, metric: Metric) -> (SolveResult<Solution>, SolveStats) {
+        bidirectional_solve_with_stats(initial_game, limits, metric)
+    }
+}
+
+// Grow `side` by one level. If it runs into `other`, returns the pair of
+// states (one on each side) that make the shortest path through this level.
+fn expand(side: &mut Side, other: &Side, limits: &SolveLimits, metric: Metric, stats: &mut SolveStats) -> Result<Option<(usize, usize)>, Limit> {
+    let mut next_frontier = Vec::new();
+    let mut meeting: Option<(usize, usize)> = None;
+
+    let frontier = side.frontier.clone();
+    for index in frontier {
+        if let Some(limit) = limits.exceeded(side.index.len() + other.index.len()) {
+            return Err(limit);
+        }
+
+        let next_games = successors(&side.arena[index].game, metric);
+        stats.expand(next_games.len());
+
+        for (piece_move, mut next_game) in next_games {
+            let key = next_game.key();
+            if side.index.contains_key(&key) {
+                continue;
+            }
+
+            next_game.parent = Some(index);
+            let next_index = side.arena.len();
+            side.arena.push(Node {game: next_game, last_move: Some(piece_move), cost: side.depth + 1});
+            next_frontier.push(next_index);
+
+            if let Some(&other_index) = other.index.get(&key) {
+                let shorter = match meeting {
+                    Some((_, best)) => other.arena[other_index].cost < other.arena[best].cost,
+                    None => true
+                };
+                if shorter {
+                    meeting = Some((next_index, other_index));
+                }
+            }
+
+            side.index.insert(key, next_index);
+        }
+    }
+
+    side.frontier = next_frontier;
+    side.depth += 1;
+
+    Ok(meeting)
+}
+
+// The path from the start to `from_start`, then on from `from_goal` (the same
+// arrangement reached from the other side) by undoing the backward moves.
+fn join(forward: &Side, from_start: usize, backward: &Side, from_goal: usize, metric: Metric) -> Solution {
+    let mut solution = unwind(&forward.arena, from_start, Metric::Slide);
+
+    let mut current = from_goal;
+    while let Some(parent) = backward.arena[current].game.parent {
+        let undone = backward.arena[current].last_move.as_ref().unwrap();
+        solution.moves.push(Move {piece: undone.piece, direction: undone.direction.opposite(), steps: undone.steps});
+        solution.games.push(backward.arena[parent].game.clone());
+        current = parent;
+    }
+
+    if metric == Metric::Step {
+        merge_steps(solution)
+    } else {
+        solution
+    }
+}
+
+// Every arrangement of the pieces that leaves the exit lane clear and that
+// `game` could conceivably reach. Each piece only ever slides along one line
+// and can't get past anything else on that line, which rules out most
+// arrangements before they are built. Some of what is left may still be
+// unreachable; the backward search just never meets those.
+fn goal_states(game: &Game, limits: &SolveLimits) -> Result<Vec<Game>, Limit> {
+    let mut goals = Vec::new();
+    let mut placed = game.clone();
+    let mut occupied: Vec<Vec<bool>> = game.board.matrix.iter()
+        .map(|row| row.iter().map(|&value| value == WALL).collect())
+        .collect();
+
+    place(game, 0, &mut placed, &mut occupied, &mut goals, limits)?;
+
+    Ok(goals)
+}
+
+fn place(game: &Game, index: usize, placed: &mut Game, occupied: &mut Vec<Vec<bool>>, goals: &mut Vec<Game>, limits: &SolveLimits) -> Result<(), Limit> {
+    if index == game.pieces.len() {
+        let mut goal = placed.clone();
+        goal.redraw();
+        goals.push(goal);
+
+        return match limits.exceeded(goals.len()) {
+            Some(limit) => Err(limit),
+            None => Ok(())
+        };
+    }
+
+    for candidate in positions(game, index) {
+        if !fits(&candidate, occupied) || !keeps_order(game, placed, index, &candidate) {
+            continue;
+        }
+
+        placed.pieces[index] = candidate.clone();
+        mark(&candidate, occupied, true);
+
+        // Once the prisoner is down nothing else may sit in its way. Only
+        // walls have been marked by then, and one in the lane shuts it.
+        let lane = if index == 0 { placed.exit_lane() } else { Vec::new() };
+        if lane.iter().any(|cell| occupied[cell.y as usize][cell.x as usize]) {
+            mark(&candidate, occupied, false);
+            continue;
+        }
+        for cell in &lane {
+            occupied[cell.y as usize][cell.x as usize] = true;
+        }
+
+        let placed_rest = place(game, index + 1, placed, occupied, goals, limits);
+
+        for cell in &lane {
+            occupied[cell.y as usize][cell.x as usize] = false;
+        }
+        mark(&candidate, occupied, false);
+
+        placed_rest?;
+    }
+
+    placed.pieces[index] = game.pieces[index].clone();
+    Ok(())
+}
+
+// Everywhere piece `index` could be along the lines it slides on.
+fn positions(game: &Game, index: usize) -> Vec<Piece> {
+    let piece = &game.pieces[index];
+    let directions = game.directions(PieceId::from_index(index));
+    let (width, height) = (piece.end.x - piece.begin.x, piece.end.y - piece.begin.y);
+
+    let xs: Vec<u8> = if directions.iter().any(|direction| direction.is_horizontal()) {
+        (0..(game.width as u8 - width)).collect()
+    } else {
+        vec![piece.begin.x]
+    };
+    let ys: Vec<u8> = if directions.iter().any(|direction| !direction.is_horizontal()) {
+        (0..(game.height as u8 - height)).collect()
+    } else {
+        vec![piece.begin.y]
+    };
+
+    let mut positions = Vec::new();
+    for &y in &ys {
+        for &x in &xs {
+            let mut position = piece.clone();
+            position.begin = Point {x: x, y: y};
+            position.end = Point {x: x + width, y: y + height};
+            positions.push(position);
+        }
+    }
+
+    positions
+}
+
+fn fits(piece: &Piece, occupied: &[Vec<bool>]) -> bool {
+    for x in piece.begin.x..(piece.end.x + 1) {
+        for y in piece.begin.y..(piece.end.y + 1) {
+            if occupied[y as usize][x as usize] {
+                return false;
+            }
+        }
+    }
+
+    true
+}
+
+fn mark(piece: &Piece, occupied: &mut [Vec<bool>], value: bool) {
+    for x in piece.begin.x..(piece.end.x + 1) {
+        for y in piece.begin.y..(piece.end.y + 1) {
+            occupied[y as usize][x as usize] = value;
+        }
+    }
+}
+
+// Whether `candidate` is still on the same side of every piece already placed
+// that shares a line with it as piece `index` is in `game`.
+fn keeps_order(game: &Game, placed: &Game, index: usize, candidate: &Piece) -> bool {
+    let id = PieceId::from_index(index);
+    for other in 0..index {
+        let other_id = PieceId::from_index(other);
+        let horizontal = match (game.is_disabled(id), game.is_disabled(other_id)) {
+            (true, true) => continue,
+            (true, false) => game.moves_horizontally(other_id),
+            (false, true) => game.moves_horizontally(id),
+            (false, false) => {
+                if game.moves_horizontally(id) != game.moves_horizontally(other_id) {
+                    continue;
+                }
+                game.moves_horizontally(id)
+            }
+        };
+
+        let (original, original_other) = (&game.pieces[index], &game.pieces[other]);
+        let placed_other = &placed.pieces[other];
+
+        let same_line = if horizontal {
+            original.begin.y <= original_other.end.y && original_other.begin.y <= original.end.y
+        } else {
+            original.begin.x <= original_other.end.x && original_other.begin.x <= original.end.x
+        };
+        if !same_line {
+            continue;
+        }
+
+        let before = if horizontal {
+            (original.begin.x < original_other.begin.x, candidate.begin.x < placed_other.begin.x)
+        } else {
+            (original.begin.y < original_other.begin.y, candidate.begin.y < placed_other.begin.y)
+        };
+        if before.0 != before.1 {
+            return false;
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use game::{Game, PieceKind};
+    use solver::{extra_solve, Metric, SolveLimits, SolveResult};
+    use solver::fixtures::tricky_game;
+    use super::{bidirectional_solve, goal_states};
+
+    #[test]
+    fn meets_in_the_middle_at_the_optimal_length() {
+        let game = tricky_game();
+
+        for &metric in &[Metric::Slide, Metric::Step] {
+            let expected = extra_solve(&game, &SolveLimits::default(), metric).unwrap();
+            let solution = bidirectional_solve(&game, &SolveLimits::default(), metric).unwrap();
+
+            assert_eq!(solution.cost(metric), expected.cost(metric));
+            assert_eq!(solution.games.len(), solution.moves.len() + 1);
+
+            let mut replayed = game.clone();
+            for piece_move in &solution.moves {
+                assert_eq!(replayed.apply(piece_move), true);
+            }
+            assert_eq!(replayed.can_exit(), true);
+            assert_eq!(&replayed.board.matrix, &solution.games.last().unwrap().board.matrix);
+        }
+    }
+
+    #[test]
+    fn only_builds_goals_pieces_could_reach() {
+        let game = Game::array_to_game(vec![
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 2, 0],
+            vec![0, 0, 1, 1, 2, 3],
+            vec![0, 0, 0, 0, 2, 3],
+            vec![0, 0, 0, 0, 0, 3],
+            vec![0, 0, 0, 0, 0, 0]
+        ], true, Vec::new(), Vec::new());
+
+        // Wherever the prisoner ends up, both other pieces have to be below it
+        let goals = goal_states(&game, &SolveLimits::default()).unwrap();
+
+        assert_eq!(goals.len(), 5);
+        for goal in &goals {
+            assert_eq!(goal.can_exit(), true);
+            assert_eq!(goal.pieces[1].begin.y, 3);
+            assert_eq!(goal.pieces[2].begin.y, 3);
+        }
+    }
+
+    #[test]
+    fn proves_unsolvable_when_no_goal_exists() {
+        let game = Game::array_to_game(vec![
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![1, 1, 0, 0, 0, 2],
+            vec![0, 0, 0, 0, 0, 2],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+        ], true, vec![2], Vec::new());
+
+        assert_eq!(bidirectional_solve(&game, &SolveLimits::default(), Metric::Slide).map(|_| ()), SolveResult::ProvenUnsolvable);
+    }
+
+    #[test]
+    fn solves_klotski_puzzles_like_extra_solve() {
+        let mut game = Game::array_to_game(vec![
+            vec![2, 2, 0, 3, 0, 0],
+            vec![2, 2, 0, 3, 0, 4],
+            vec![1, 1, 5, 6, 6, 4],
+            vec![0, 0, 5, 0, 0, 0],
+            vec![7, 0, 0, 0, 8, 8],
+            vec![0, 0, 0, 9, 8, 8]
+        ], true, Vec::new(), Vec::new());
+        for piece in game.pieces.iter_mut().skip(1) {
+            piece.kind = PieceKind::Free;
+        }
+
+        for &metric in &[Metric::Slide, Metric::Step] {
+            let expected = extra_solve(&game, &SolveLimits::default(), metric).unwrap();
+            let solution = bidirectional_solve(&game, &SolveLimits::default(), metric).unwrap();
+
+            assert_eq!(solution.cost(metric), expected.cost(metric));
+
+            let mut replayed = game.clone();
+            for piece_move in &solution.moves {
+                assert_eq!(replayed.apply(piece_move), true);
+            }
+            assert_eq!(replayed.can_exit(), true);
+        }
+    }
+}
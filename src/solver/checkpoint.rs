@@ -0,0 +1,200 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use rustc_serialize::json;
+
+use game::{Game, Move, Piece, Point};
+use super::{continue_search, Frontier, Limit, Metric, Node, Solution, SolveLimits, SolveResult, SolveStats};
+
+// A paused `extra_solve`, written out as JSON. Boards are kept as their
+// `Game::key`, and rebuilt from the game the search started from.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(RustcDecodable, RustcEncodable)]
+pub struct Checkpoint {
+    metric: Metric,
+    symmetry: bool,
+    // The puzzle the search started from, so it can't be resumed on another
+    width: usize,
+    height: usize,
+    exit: bool,
+    walls: Vec<Point>,
+    pieces: Vec<Piece>,
+    nodes: Vec<SavedNode>,
+    best: Vec<(Vec<u8>, usize)>,
+    queue: Vec<usize>,
+    depth_pruned: bool
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(RustcDecodable, RustcEncodable)]
+struct SavedNode {
+    key: Vec<u8>,
+    parent: Option<usize>,
+    last_move: Option<Move>,
+    cost: usize
+}
+
+impl Checkpoint {
+    fn save_frontier(frontier: &Frontier, metric: Metric) -> Checkpoint {
+        let start = &frontier.arena[0].game;
+
+        Checkpoint {
+            metric: metric,
+            symmetry: frontier.symmetry,
+            width: start.width,
+            height: start.height,
+            exit: start.exit,
+            walls: start.walls(),
+            pieces: start.pieces.clone(),
+            nodes: frontier.arena.iter().map(|node| SavedNode {
+                key: node.game.key(),
+                parent: node.game.parent,
+                last_move: node.last_move.clone(),
+                cost: node.cost
+            }).collect(),
+            best: frontier.best.iter().map(|(key, &cost)| (key.clone(), cost)).collect(),
+            queue: frontier.queue.iter().cloned().collect(),
+            depth_pruned: frontier.depth_pruned
+        }
+    }
+
+    fn load_frontier(&self, initial_game: &Game) -> Frontier {
+        Frontier {
+            arena: self.nodes.iter().map(|node| {
+                let mut game = initial_game.with_key(&node.key);
+                game.parent = node.parent;
+                Node {game: game, last_move: node.last_move.clone(), cost: node.cost}
+            }).collect(),
+            best: self.best.iter().cloned().collect::<HashMap<Vec<u8>, usize>>(),
+            queue: self.queue.iter().cloned().collect::<VecDeque<usize>>(),
+            depth_pruned: self.depth_pruned,
+            symmetry: self.symmetry
+        }
+    }
+
+    fn made_from(&self, game: &Game) -> bool {
+        self.width == game.width && self.height == game.height && self.exit == game.exit &&
+            self.walls == game.walls() && self.pieces == game.pieces
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let encoded = json::encode(self).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        File::create(path)?.write_all(encoded.as_bytes())
+    }
+
+    pub fn load(path: &Path) -> io::Result<Checkpoint> {
+        let mut encoded = String::new();
+        File::open(path)?.read_to_string(&mut encoded)?;
+        json::decode(&encoded).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+}
+
+pub enum Progress {
+    Finished(SolveResult<Solution>),
+    // A state or time limit was hit. Resuming from the checkpoint with more
+    // room gives the same answer a single uninterrupted search would have.
+    Paused(Checkpoint)
+}
+
+// `extra_solve`, but running into `SolveLimits::max_states` or
+// `SolveLimits::deadline` hands back a checkpoint instead of giving up.
+// Pass that checkpoint back in, with the same game, to carry on.
+//
+// A checkpoint keeps the metric and symmetry setting it was made with, and
+// resuming from one with a different setting is an error.
+pub fn resumable_solve(initial_game: &Game, limits: &SolveLimits, metric: Metric, symmetry: bool, checkpoint: Option<&Checkpoint>) -> Result<Progress, String> {
+    let mut frontier = match checkpoint {
+        Some(checkpoint) => {
+            if checkpoint.metric != metric || checkpoint.symmetry != symmetry {
+                return Err(format!("checkpoint was made with {:?} and symmetry {}", checkpoint.metric, checkpoint.symmetry));
+            }
+            if !checkpoint.made_from(initial_game) {
+                return Err("checkpoint was made from a different game".to_string());
+            }
+
+            checkpoint.load_frontier(initial_game)
+        },
+        None => Frontier::new(initial_game, metric, symmetry)
+    };
+
+    match continue_search(&mut frontier, limits, metric, &mut SolveStats::default()) {
+        SolveResult::LimitExceeded(Limit::States) | SolveResult::LimitExceeded(Limit::Deadline) => {
+            Ok(Progress::Paused(Checkpoint::save_frontier(&frontier, metric)))
+        },
+        result => Ok(Progress::Finished(result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use game::{Game, Point};
+    use solver::{extra_solve, Metric, SolveLimits};
+    use solver::fixtures::{tricky_game, TempDir};
+    use super::{resumable_solve, Checkpoint, Progress};
+
+    #[test]
+    fn resuming_gives_the_same_solution() {
+        let game = tricky_game();
+        let expected = extra_solve(&game, &SolveLimits::default(), Metric::Step).unwrap();
+        let dir = TempDir::new("resuming_gives_the_same_solution");
+        let path = dir.path.join("checkpoint.json");
+
+        let mut checkpoint: Option<Checkpoint> = None;
+        let mut pauses = 0;
+        let mut max_states = 0;
+
+        let solution = loop {
+            max_states += 2000;
+            let limits = SolveLimits {max_states: Some(max_states), ..SolveLimits::default()};
+
+            match resumable_solve(&game, &limits, Metric::Step, false, checkpoint.as_ref()).unwrap() {
+                Progress::Finished(result) => break result.unwrap(),
+                Progress::Paused(paused) => {
+                    // Go through the file every time, as a restarted process would
+                    paused.save(&path).unwrap();
+                    checkpoint = Some(Checkpoint::load(&path).unwrap());
+                    pauses += 1;
+                }
+            }
+        };
+
+        assert!(pauses > 1);
+        assert_eq!(solution.moves, expected.moves);
+        for (game, expected) in solution.games.iter().zip(expected.games.iter()) {
+            assert_eq!(game.board.matrix, expected.board.matrix);
+        }
+    }
+
+    #[test]
+    fn refuses_a_checkpoint_from_another_search() {
+        let game = tricky_game();
+        let limits = SolveLimits {max_states: Some(10), ..SolveLimits::default()};
+
+        let checkpoint = match resumable_solve(&game, &limits, Metric::Slide, false, None).unwrap() {
+            Progress::Paused(checkpoint) => checkpoint,
+            Progress::Finished(_) => panic!("should have paused")
+        };
+
+        assert!(resumable_solve(&game, &limits, Metric::Step, false, Some(&checkpoint)).is_err());
+        assert!(resumable_solve(&game, &limits, Metric::Slide, true, Some(&checkpoint)).is_err());
+
+        let mut other = game.clone();
+        assert_eq!(other.move_piece(2, true, 1), true);
+        assert!(resumable_solve(&other, &limits, Metric::Slide, false, Some(&checkpoint)).is_err());
+
+        // The same positions, but a puzzle that plays differently
+        let mut walled = game.clone();
+        assert_eq!(walled.add_wall(Point {x: 3, y: 0}), true);
+        assert!(resumable_solve(&walled, &limits, Metric::Slide, false, Some(&checkpoint)).is_err());
+
+        let disabled = Game::array_to_game(game.board.matrix.clone(), true, vec![2], Vec::new());
+        assert!(resumable_solve(&disabled, &limits, Metric::Slide, false, Some(&checkpoint)).is_err());
+
+        assert!(resumable_solve(&game, &limits, Metric::Slide, false, Some(&checkpoint)).is_ok());
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+
+use game::{Game, Move};
+use super::{successors, Limit, Metric, Solution, SolveLimits, SolveResult};
+
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct SolutionCount {
+    // Slides in every optimal solution.
+    pub length: usize,
+    // How many different move sequences of that length reach an exit.
+    pub count: u64,
+    // The first of them, up to however many were asked for.
+    pub solutions: Vec<Solution>
+}
+
+// Breadth first search that remembers every way into each state from the
+// level before, rather than just the first, and how many shortest paths
+// from the start end at it.
+pub fn count_solutions(initial_game: &Game, limits: &SolveLimits, max_listed: usize) -> SolveResult<SolutionCount> {
+    let mut games: Vec<Game> = Vec::new();
+    let mut paths: Vec<u64> = Vec::new();
+    let mut parents: Vec<Vec<(usize, Move)>> = Vec::new();
+    let mut depths: Vec<usize> = Vec::new();
+    let mut index: HashMap<Vec<u8>, usize> = HashMap::new();
+
+    let mut root = initial_game.clone();
+    root.parent = None;
+    index.insert(root.key(), 0);
+    games.push(root);
+    paths.push(1);
+    parents.push(Vec::new());
+    depths.push(0);
+
+    let mut level: Vec<usize> = vec![0];
+    let mut depth = 0;
+
+    loop {
+        let goals: Vec<usize> = level.iter().cloned().filter(|&i| games[i].can_exit()).collect();
+        if !goals.is_empty() {
+            let mut count: u64 = 0;
+            for &goal in &goals {
+                count = count.saturating_add(paths[goal]);
+            }
+
+            let mut solutions = Vec::new();
+            for &goal in &goals {
+                list(&games, &parents, goal, &mut Vec::new(), &mut solutions, max_listed);
+            }
+
+            return SolveResult::Solved(SolutionCount {length: depth, count: count, solutions: solutions});
+        }
+
+        if level.is_empty() {
+            return SolveResult::ProvenUnsolvable;
+        }
+        if !limits.allows_depth(depth + 1) {
+            return SolveResult::LimitExceeded(Limit::Depth);
+        }
+
+        let mut next_level = Vec::new();
+        for &current in &level {
+            if let Some(limit) = limits.exceeded(games.len()) {
+                return SolveResult::LimitExceeded(limit);
+            }
+
+            for (piece_move, next_game) in successors(&games[current], Metric::Slide) {
+                let key = next_game.key();
+                let next = match index.get(&key) {
+                    Some(&next) => next,
+                    None => {
+                        index.insert(key, games.len());
+                        games.push(next_game);
+                        paths.push(0);
+                        parents.push(Vec::new());
+                        depths.push(depth + 1);
+                        next_level.push(games.len() - 1);
+                        games.len() - 1
+                    }
+                };
+
+                // Only count ways in from the level directly above
+                if depths[next] == depth + 1 {
+                    paths[next] = paths[next].saturating_add(paths[current]);
+                    parents[next].push((current, piece_move));
+                }
+            }
+        }
+
+        level = next_level;
+        depth += 1;
+    }
+}
+
+// Walk every chain of parents from `index` back to the start, collecting
+// the moves along the way, until `max_listed` solutions have been built.
+fn list(games: &[Game], parents: &[Vec<(usize, Move)>], index: usize, moves: &mut Vec<Move>, solutions: &mut Vec<Solution>, max_listed: usize) {
+    if solutions.len() >= max_listed {
+        return;
+    }
+
+    if parents[index].is_empty() {
+        let mut solution = Solution {games: vec![games[index].clone()], moves: Vec::new()};
+        for piece_move in moves.iter().rev() {
+            let mut next_game = solution.games.last().unwrap().clone();
+            next_game.apply(piece_move);
+            solution.games.push(next_game);
+            solution.moves.push(piece_move.clone());
+        }
+
+        solutions.push(solution);
+        return;
+    }
+
+    for &(parent, ref piece_move) in &parents[index] {
+        moves.push(piece_move.clone());
+        list(games, parents, parent, moves, solutions, max_listed);
+        moves.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use game::Game;
+    use solver::{extra_solve, Metric, SolveLimits};
+    use solver::fixtures::tricky_game;
+    use super::count_solutions;
+
+    #[test]
+    fn counts_every_order_of_the_same_moves() {
+        // Pieces 2 and 3 both have to move out of the way, in either order
+        let game = Game::array_to_game(vec![
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 2, 0],
+            vec![0, 0, 1, 1, 2, 3],
+            vec![0, 0, 0, 0, 2, 3],
+            vec![0, 0, 0, 0, 0, 3],
+            vec![0, 0, 0, 0, 0, 0]
+        ], true, Vec::new(), Vec::new());
+
+        let counted = count_solutions(&game, &SolveLimits::default(), 10).unwrap();
+
+        assert_eq!(counted.length, 2);
+        assert_eq!(counted.count, 2);
+        assert_eq!(counted.solutions.len(), 2);
+        assert!(counted.solutions[0].moves != counted.solutions[1].moves);
+
+        for solution in &counted.solutions {
+            let mut replayed = game.clone();
+            for piece_move in &solution.moves {
+                assert_eq!(replayed.apply(piece_move), true);
+            }
+            assert_eq!(replayed.can_exit(), true);
+            assert_eq!(&replayed.board.matrix, &solution.games.last().unwrap().board.matrix);
+        }
+    }
+
+    #[test]
+    fn lists_no_more_than_asked() {
+        let game = tricky_game();
+
+        let counted = count_solutions(&game, &SolveLimits::default(), 1).unwrap();
+        let expected = extra_solve(&game, &SolveLimits::default(), Metric::Slide).unwrap();
+
+        assert_eq!(counted.length, expected.moves.len());
+        assert!(counted.count >= 1);
+        assert_eq!(counted.solutions.len(), 1);
+        assert_eq!(counted.solutions[0].moves.len(), counted.length);
+    }
+}
@@ -0,0 +1,394 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use game::{Game, Move};
+use super::{successors, Limit, Metric, Solution, SolveLimits, SolveResult};
+
+// How many states there are at one depth and how many of them can exit.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct Layer {
+    pub states: usize,
+    pub exits: usize
+}
+
+// Breadth first search that keeps each level in a file of sorted `Game::key`s
+// under `dir` rather than in memory. Only `max_in_memory` keys are held at
+// once; beyond that they are sorted and spilled to a run file, and the runs
+// are merged into the next level.
+//
+// Every move can be undone, so anything a level leads to is in the level
+// before it, the level itself or the next one. Removing the first two while
+// merging is enough to drop every state seen before, without ever keeping
+// the whole visited set.
+//
+// `dir` must exist and not be shared with another search. The files are
+// removed when the search finishes, however it finishes. Only
+// `Metric::Slide` is supported.
+pub fn disk_solve(initial_game: &Game, limits: &SolveLimits, dir: &Path, max_in_memory: usize) -> io::Result<SolveResult<Solution>> {
+    let mut levels = Levels::new(initial_game, dir, max_in_memory)?;
+
+    match levels.search(limits, true)? {
+        Ok(_) => match levels.first_exit {
+            Some((depth, ref key)) => Ok(SolveResult::Solved(levels.walk_back(key.clone(), depth)?)),
+            None => Ok(SolveResult::ProvenUnsolvable)
+        },
+        Err(limit) => Ok(SolveResult::LimitExceeded(limit))
+    }
+}
+
+// Every level reachable from `initial_game`, stored the same way as in
+// `disk_solve`, for analysing boards too big for `explore`.
+pub fn disk_explore(initial_game: &Game, limits: &SolveLimits, dir: &Path, max_in_memory: usize) -> io::Result<Result<Vec<Layer>, Limit>> {
+    Levels::new(initial_game, dir, max_in_memory)?.search(limits, false)
+}
+
+struct Levels<'a> {
+    initial_game: Game,
+    dir: &'a Path,
+    max_in_memory: usize,
+    // Length of every key, which is the size of a record in each file.
+    width: usize,
+    files: Vec<PathBuf>,
+    // Sorted runs of the next level that haven't been merged yet.
+    runs: Vec<PathBuf>,
+    layers: Vec<Layer>,
+    first_exit: Option<(usize, Vec<u8>)>
+}
+
+impl<'a> Levels<'a> {
+    fn new(initial_game: &Game, dir: &'a Path, max_in_memory: usize) -> io::Result<Levels<'a>> {
+        let mut root = initial_game.clone();
+        root.parent = None;
+        let key = root.key();
+
+        let mut levels = Levels {
+            initial_game: root,
+            dir: dir,
+            max_in_memory: if max_in_memory == 0 { 1 } else { max_in_memory },
+            width: key.len(),
+            files: Vec::new(),
+            runs: Vec::new(),
+            layers: Vec::new(),
+            first_exit: None
+        };
+
+        let path = levels.level_path(0);
+        levels.files.push(path.clone());
+        let mut writer = BufWriter::new(File::create(&path)?);
+        writer.write_all(&key)?;
+        writer.flush()?;
+
+        Ok(levels)
+    }
+
+    fn level_path(&self, depth: usize) -> PathBuf {
+        self.dir.join(format!("level-{}.keys", depth))
+    }
+
+    fn run_path(&self, run: usize) -> PathBuf {
+        self.dir.join(format!("run-{}.keys", run))
+    }
+
+    // Work through level after level until one is empty, or, with
+    // `stop_at_exit`, until a level holds a game the prisoner can leave.
+    fn search(&mut self, limits: &SolveLimits, stop_at_exit: bool) -> io::Result<Result<Vec<Layer>, Limit>> {
+        let mut states = 1;
+        let mut depth = 0;
+
+        loop {
+            if let Some(limit) = limits.exceeded(states) {
+                return Ok(Err(limit));
+            }
+
+            let layer = self.expand(depth, limits, stop_at_exit)?;
+            let exits = layer.exits;
+            self.layers.push(layer);
+
+            if exits > 0 && stop_at_exit {
+                return Ok(Ok(self.layers.clone()));
+            }
+            if !limits.allows_depth(depth + 1) {
+                return Ok(Err(Limit::Depth));
+            }
+
+            let next_states = self.merge(depth + 1)?;
+            if next_states == 0 {
+                return Ok(Ok(self.layers.clone()));
+            }
+
+            states += next_states;
+            depth += 1;
+        }
+    }
+
+    // Read level `depth`, counting it and writing everything one move away
+    // into sorted run files.
+    fn expand(&mut self, depth: usize, limits: &SolveLimits, stop_at_exit: bool) -> io::Result<Layer> {
+        let mut layer = Layer {states: 0, exits: 0};
+        let mut buffer: Vec<Vec<u8>> = Vec::new();
+        let mut reader = KeyReader::open(&self.files[depth], self.width)?;
+
+        while let Some(key) = reader.next()? {
+            let game = self.initial_game.with_key(&key);
+            layer.states += 1;
+
+            if game.can_exit() {
+                layer.exits += 1;
+                if self.first_exit.is_none() {
+                    self.first_exit = Some((depth, key));
+                }
+            }
+            if (layer.exits > 0 && stop_at_exit) || !limits.allows_depth(depth + 1) {
+                continue;
+            }
+
+            for (_, next_game) in successors(&game, Metric::Slide) {
+                buffer.push(next_game.key());
+
+                if buffer.len() >= self.max_in_memory {
+                    self.write_run(&mut buffer)?;
+                }
+            }
+        }
+
+        if !buffer.is_empty() {
+            self.write_run(&mut buffer)?;
+        }
+
+        Ok(layer)
+    }
+
+    fn write_run(&mut self, buffer: &mut Vec<Vec<u8>>) -> io::Result<()> {
+        buffer.sort();
+        buffer.dedup();
+
+        let path = self.run_path(self.runs.len());
+        self.runs.push(path.clone());
+        let mut writer = BufWriter::new(File::create(&path)?);
+        for key in buffer.iter() {
+            writer.write_all(key)?;
+        }
+        writer.flush()?;
+
+        buffer.clear();
+        Ok(())
+    }
+
+    // Merge the runs into level `depth`, leaving out anything already in the
+    // two levels before it. Returns how many states the new level holds.
+    fn merge(&mut self, depth: usize) -> io::Result<usize> {
+        let mut readers = Vec::new();
+        let mut heap = BinaryHeap::new();
+
+        for (i, run) in self.runs.iter().enumerate() {
+            let mut reader = KeyReader::open(run, self.width)?;
+            if let Some(key) = reader.next()? {
+                heap.push(Reverse((key, i)));
+            }
+            readers.push(reader);
+        }
+
+        let mut seen = Vec::new();
+        for previous in self.files.iter().rev().take(2) {
+            let mut reader = KeyReader::open(previous, self.width)?;
+            let first = reader.next()?;
+            seen.push((reader, first));
+        }
+
+        let path = self.level_path(depth);
+        self.files.push(path.clone());
+        let mut writer = BufWriter::new(File::create(&path)?);
+        let mut last: Option<Vec<u8>> = None;
+        let mut count = 0;
+
+        while let Some(Reverse((key, i))) = heap.pop() {
+            if let Some(next) = readers[i].next()? {
+                heap.push(Reverse((next, i)));
+            }
+
+            if last.as_ref() == Some(&key) {
+                continue;
+            }
+
+            let mut old = false;
+            for &mut (ref mut reader, ref mut current) in seen.iter_mut() {
+                while current.as_ref().is_some_and(|current| current < &key) {
+                    *current = reader.next()?;
+                }
+                if current.as_ref() == Some(&key) {
+                    old = true;
+                }
+            }
+
+            if !old {
+                writer.write_all(&key)?;
+                count += 1;
+            }
+            last = Some(key);
+        }
+        writer.flush()?;
+
+        for run in self.runs.drain(..) {
+            fs::remove_file(run)?;
+        }
+
+        Ok(count)
+    }
+
+    // Find a way back from `key` at `depth` to the start, one level at a
+    // time, by looking for a game one move away in the level before.
+    fn walk_back(&self, key: Vec<u8>, depth: usize) -> io::Result<Solution> {
+        let mut moves: Vec<Move> = Vec::new();
+        let mut current = self.initial_game.with_key(&key);
+
+        for previous in (0..depth).rev() {
+            let mut found = None;
+            for (piece_move, previous_game) in successors(&current, Metric::Slide) {
+                if contains(&self.files[previous], &previous_game.key(), self.width)? {
+                    found = Some((piece_move, previous_game));
+                    break;
+                }
+            }
+
+            let (piece_move, previous_game) = found.expect("every state has a parent in the level before it");
+            moves.push(Move {piece: piece_move.piece, direction: piece_move.direction.opposite(), steps: piece_move.steps});
+            current = previous_game;
+        }
+
+        let mut solution = Solution {games: vec![self.initial_game.clone()], moves: Vec::new()};
+        for piece_move in moves.into_iter().rev() {
+            let mut next_game = solution.games.last().unwrap().clone();
+            next_game.apply(&piece_move);
+            solution.games.push(next_game);
+            solution.moves.push(piece_move);
+        }
+
+        Ok(solution)
+    }
+
+}
+
+// Whether the search finished, ran into a limit or failed part way, nothing
+// is left behind in `dir`. Files that were never created are skipped over.
+impl<'a> Drop for Levels<'a> {
+    fn drop(&mut self) {
+        for file in self.files.iter().chain(self.runs.iter()) {
+            let _ = fs::remove_file(file);
+        }
+    }
+}
+
+// Reads a file of fixed length keys one at a time.
+struct KeyReader {
+    reader: BufReader<File>,
+    width: usize
+}
+
+impl KeyReader {
+    fn open(path: &Path, width: usize) -> io::Result<KeyReader> {
+        Ok(KeyReader {reader: BufReader::new(File::open(path)?), width: width})
+    }
+
+    fn next(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut key = vec![0; self.width];
+
+        match self.reader.read_exact(&mut key) {
+            Ok(()) => Ok(Some(key)),
+            Err(ref error) if error.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(error) => Err(error)
+        }
+    }
+}
+
+// Binary search a sorted file of keys without reading all of it.
+fn contains(path: &Path, key: &[u8], width: usize) -> io::Result<bool> {
+    let mut file = File::open(path)?;
+    let mut record = vec![0; width];
+
+    let mut low = 0;
+    let mut high = file.metadata()?.len() as usize / width;
+
+    while low < high {
+        let middle = (low + high) / 2;
+        file.seek(SeekFrom::Start((middle * width) as u64))?;
+        file.read_exact(&mut record)?;
+
+        match record.as_slice().cmp(key) {
+            Ordering::Less => low = middle + 1,
+            Ordering::Greater => high = middle,
+            Ordering::Equal => return Ok(true)
+        }
+    }
+
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use solver::{explore, extra_solve, Limit, Metric, SolveLimits, SolveResult};
+    use solver::fixtures::{medium_game, TempDir};
+    use super::{disk_explore, disk_solve};
+
+    #[test]
+    fn solves_as_short_as_breadth_first() {
+        let temp = TempDir::new("solves_as_short_as_breadth_first");
+        let dir = &temp.path;
+        let game = medium_game();
+
+        // Small enough that every level is spread over several runs
+        let solution = disk_solve(&game, &SolveLimits::default(), dir, 50).unwrap().unwrap();
+        let expected = extra_solve(&game, &SolveLimits::default(), Metric::Slide).unwrap();
+
+        assert_eq!(solution.moves.len(), expected.moves.len());
+        let mut replayed = game.clone();
+        for piece_move in &solution.moves {
+            assert_eq!(replayed.apply(piece_move), true);
+        }
+        assert_eq!(replayed.can_exit(), true);
+
+        assert_eq!(fs::read_dir(dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn explores_the_same_levels_as_memory() {
+        let temp = TempDir::new("explores_the_same_levels_as_memory");
+        let dir = &temp.path;
+        let game = medium_game();
+
+        let layers = disk_explore(&game, &SolveLimits::default(), dir, 50).unwrap().unwrap();
+        let space = explore(&game, &SolveLimits::default()).unwrap();
+
+        assert_eq!(layers.iter().map(|layer| layer.states).sum::<usize>(), space.games.len());
+        assert_eq!(layers.iter().map(|layer| layer.exits).sum::<usize>(),
+                   space.distances.iter().filter(|&&distance| distance == Some(0)).count());
+        assert_eq!(layers[0].states, 1);
+
+        let shallow = SolveLimits {max_depth: Some(2), ..SolveLimits::default()};
+        assert_eq!(disk_explore(&game, &shallow, dir, 50).unwrap(), Err(Limit::Depth));
+        assert_eq!(disk_solve(&game, &shallow, dir, 50).unwrap().map(|_| ()), SolveResult::LimitExceeded(Limit::Depth));
+
+        assert_eq!(fs::read_dir(dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn cleans_up_after_a_failed_search() {
+        let temp = TempDir::new("cleans_up_after_a_failed_search");
+        let dir = &temp.path;
+
+        // The first run can't be written where a directory is in the way
+        fs::create_dir(dir.join("run-0.keys")).unwrap();
+        assert!(disk_solve(&medium_game(), &SolveLimits::default(), dir, 50).is_err());
+
+        let left: Vec<_> = fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        assert_eq!(left, vec!["run-0.keys"]);
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::{HashMap, VecDeque};
+
+use game::{Game, Move};
+use super::{successors, Limit, Metric, SolveLimits};
+
+// Every arrangement reachable from a starting game, with the moves between
+// them and how far each one is from being able to exit.
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct StateSpace {
+    // `games[0]` is the game exploration started from.
+    pub games: Vec<Game>,
+    // Fewest slides from each game to one the prisoner can leave, or `None`
+    // if it never can.
+    pub distances: Vec<Option<usize>>,
+    // The moves out of each game and the index of the game they lead to.
+    pub neighbours: Vec<Vec<(Move, usize)>>,
+    index: HashMap<Vec<u8>, usize>
+}
+
+impl StateSpace {
+    pub fn index_of(&self, game: &Game) -> Option<usize> {
+        self.index.get(&game.key()).cloned()
+    }
+
+    // `None` if `game` isn't part of this state space or can't exit.
+    pub fn distance(&self, game: &Game) -> Option<usize> {
+        match self.index_of(game) {
+            Some(index) => self.distances[index],
+            None => None
+        }
+    }
+
+    // The game furthest from the exit that can still reach it, which makes
+    // the hardest possible start for this set of pieces.
+    pub fn hardest(&self) -> Option<usize> {
+        let mut hardest: Option<usize> = None;
+
+        for (index, distance) in self.distances.iter().enumerate() {
+            if let Some(distance) = *distance {
+                let further = match hardest {
+                    Some(best) => distance > self.distances[best].unwrap(),
+                    None => true
+                };
+                if further {
+                    hardest = Some(index);
+                }
+            }
+        }
+
+        hardest
+    }
+
+    // Games the prisoner can never leave from.
+    //
+    // Every slide can be undone, so as long as that holds a puzzle is either
+    // solvable from everywhere in it or from nowhere at all. Anything that
+    // only moves one way would change that, and these are what to look at.
+    pub fn dead_ends(&self) -> Vec<usize> {
+        (0..self.games.len()).filter(|&index| self.distances[index].is_none()).collect()
+    }
+
+    // Moves that take a game that can still be solved to a dead end, along
+    // with the index of the game they are played from.
+    pub fn trap_moves(&self) -> Vec<(usize, Move)> {
+        let mut traps = Vec::new();
+
+        for (index, neighbours) in self.neighbours.iter().enumerate() {
+            if self.distances[index].is_none() {
+                continue;
+            }
+
+            for &(ref piece_move, neighbour) in neighbours {
+                if self.distances[neighbour].is_none() {
+                    traps.push((index, piece_move.clone()));
+                }
+            }
+        }
+
+        traps
+    }
+}
+
+// Breadth first search over everything reachable from `initial_game`,
+// followed by a second breadth first search outwards from every exit
+// arrangement found to label each game with its distance.
+pub fn explore(initial_game: &Game, limits: &SolveLimits) -> Result<StateSpace, Limit> {
+    let mut space = StateSpace {
+        games: Vec::new(),
+        distances: Vec::new(),
+        neighbours: Vec::new(),
+        index: HashMap::new()
+    };
+    let mut depths: Vec<usize> = Vec::new();
+
+    let mut root = initial_game.clone();
+    root.parent = None;
+    space.index.insert(root.key(), 0);
+    space.games.push(root);
+    depths.push(0);
+
+    let mut next = 0;
+    while next < space.games.len() {
+        if let Some(limit) = limits.exceeded(space.games.len()) {
+            return Err(limit);
+        }
+
+        let mut neighbours = Vec::new();
+        for (piece_move, next_game) in successors(&space.games[next], Metric::Slide) {
+            let key = next_game.key();
+            let neighbour = match space.index.get(&key) {
+                Some(&neighbour) => neighbour,
+                None => {
+                    if !limits.allows_depth(depths[next] + 1) {
+                        return Err(Limit::Depth);
+                    }
+
+                    space.index.insert(key, space.games.len());
+                    space.games.push(next_game);
+                    depths.push(depths[next] + 1);
+                    space.games.len() - 1
+                }
+            };
+
+            neighbours.push((piece_move, neighbour));
+        }
+
+        space.neighbours.push(neighbours);
+        next += 1;
+    }
+
+    // Every move can be undone, so walking the same edges out from the exits
+    // gives each game's distance to its nearest one.
+    space.distances = vec![None; space.games.len()];
+    let mut queue: VecDeque<usize> = VecDeque::new();
+
+    for (index, game) in space.games.iter().enumerate() {
+        if game.can_exit() {
+            space.distances[index] = Some(0);
+            queue.push_back(index);
+        }
+    }
+
+    while let Some(index) = queue.pop_front() {
+        let distance = space.distances[index].unwrap();
+
+        for &(_, neighbour) in &space.neighbours[index] {
+            if space.distances[neighbour].is_none() {
+                space.distances[neighbour] = Some(distance + 1);
+                queue.push_back(neighbour);
+            }
+        }
+    }
+
+    Ok(space)
+}
+
+#[cfg(test)]
+mod tests {
+    use game::Game;
+    use solver::{extra_solve, Limit, Metric, SolveLimits};
+    use solver::fixtures::medium_game;
+    use super::explore;
+
+    #[test]
+    fn labels_every_state_with_its_distance() {
+        let game = medium_game();
+        let space = explore(&game, &SolveLimits::default()).unwrap();
+        let solution = extra_solve(&game, &SolveLimits::default(), Metric::Slide).unwrap();
+
+        assert_eq!(space.distance(&game), Some(solution.moves.len()));
+
+        for index in 0..space.games.len() {
+            assert_eq!(space.distances[index] == Some(0), space.games[index].can_exit());
+
+            for &(ref piece_move, neighbour) in &space.neighbours[index] {
+                let mut moved = space.games[index].clone();
+                assert_eq!(moved.apply(piece_move), true);
+                assert_eq!(moved.key(), space.games[neighbour].key());
+
+                let (here, there) = (space.distances[index].unwrap(), space.distances[neighbour].unwrap());
+                assert!(here <= there + 1 && there <= here + 1);
+            }
+        }
+
+        let hardest = space.hardest().unwrap();
+        assert!(space.distances[hardest].unwrap() >= solution.moves.len());
+        assert_eq!(extra_solve(&space.games[hardest], &SolveLimits::default(), Metric::Slide).unwrap().moves.len(),
+                   space.distances[hardest].unwrap());
+    }
+
+    #[test]
+    fn gives_up_past_the_state_limit() {
+        let limits = SolveLimits {max_states: Some(5), ..SolveLimits::default()};
+
+        assert_eq!(explore(&medium_game(), &limits).err(), Some(Limit::States));
+    }
+
+    #[test]
+    fn finds_dead_ends_and_the_moves_into_them() {
+        let space = explore(&medium_game(), &SolveLimits::default()).unwrap();
+        assert!(space.dead_ends().is_empty());
+        assert!(space.trap_moves().is_empty());
+
+        let stuck = Game::array_to_game(vec![
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![1, 1, 0, 0, 0, 2],
+            vec![0, 0, 0, 0, 0, 2],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+        ], true, vec![2], Vec::new());
+        let space = explore(&stuck, &SolveLimits::default()).unwrap();
+
+        assert_eq!(space.dead_ends().len(), space.games.len());
+        assert!(space.trap_moves().is_empty());
+    }
+
+    #[test]
+    fn trap_moves_lead_from_solvable_to_unsolvable() {
+        let mut space = explore(&medium_game(), &SolveLimits::default()).unwrap();
+
+        // Pretend the first move out of the start can't be taken back
+        let (ref piece_move, trapped) = space.neighbours[0][0].clone();
+        space.distances[trapped] = None;
+
+        assert_eq!(space.dead_ends(), vec![trapped]);
+        assert!(space.trap_moves().contains(&(0, piece_move.clone())));
+    }
+}
@@ -0,0 +1,65 @@
+// Boards and scratch space the tests of more than one solver share.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use game::Game;
+
+// Twelve pieces and a solution a few moves long, with room to wander.
+pub fn tricky_game() -> Game {
+    Game::array_to_game(vec![
+        vec![2, 3, 3, 0, 5, 6],
+        vec![2, 0, 4, 0, 5, 6],
+        vec![0, 0, 4, 1, 1, 7],
+        vec![11, 11, 10, 9, 0, 7],
+        vec![0, 0, 10, 9, 8, 8],
+        vec![12, 12, 12, 9, 0, 0]
+    ], true, Vec::new(), Vec::new())
+}
+
+// The sample puzzle the game has always shipped with.
+pub fn medium_game() -> Game {
+    Game::array_to_game(vec![
+        vec![2, 0, 0, 4, 0, 5],
+        vec![2, 3, 3, 4, 0, 5],
+        vec![2, 0, 1, 1, 10, 0],
+        vec![6, 6, 6, 0, 10, 11],
+        vec![0, 0, 8, 0, 10, 11],
+        vec![7, 7, 8, 9, 9, 0]
+    ], true, Vec::new(), Vec::new())
+}
+
+// Two slides from the exit, with a bar along the bottom that never has to move.
+pub fn easy_game() -> Game {
+    Game::array_to_game(vec![
+        vec![0, 0, 0, 0, 0, 0],
+        vec![0, 0, 0, 0, 2, 0],
+        vec![0, 0, 1, 1, 2, 3],
+        vec![0, 0, 0, 0, 2, 3],
+        vec![0, 0, 0, 0, 0, 3],
+        vec![4, 4, 0, 0, 0, 0]
+    ], true, Vec::new(), Vec::new())
+}
+
+// A scratch directory of the test's own, so runs side by side don't trip over
+// each other, removed again even when the test panics.
+pub struct TempDir {
+    pub path: PathBuf
+}
+
+impl TempDir {
+    pub fn new(test: &str) -> TempDir {
+        let path = env::temp_dir().join(format!("blacksheep-{}-{}", process::id(), test));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir {path: path}
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,130 @@
+use game::{Game, Move};
+use super::{explore, extra_solve, Metric, SolveLimits, SolveResult, StateSpace};
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct Hint {
+    // What to play next, or `None` if the prisoner can already leave.
+    pub next_move: Option<Move>,
+    // Fewest slides left to the exit from the game the hint was asked for.
+    pub distance: usize
+}
+
+// Solves from `game` to find the best move to play next.
+pub fn hint(game: &Game, limits: &SolveLimits) -> SolveResult<Hint> {
+    extra_solve(game, limits, Metric::Slide).map(|solution| Hint {
+        next_move: solution.moves.first().cloned(),
+        distance: solution.moves.len()
+    })
+}
+
+// Explores a puzzle the first time a hint is asked for, so every later hint
+// for any position in the same puzzle is a lookup. Asking about a different
+// puzzle explores that one instead.
+#[derive(Debug)]
+#[derive(Default)]
+pub struct HintCache {
+    space: Option<StateSpace>
+}
+
+impl HintCache {
+    pub fn new() -> HintCache {
+        HintCache {space: None}
+    }
+
+    pub fn hint(&mut self, game: &Game, limits: &SolveLimits) -> SolveResult<Hint> {
+        let cached = match self.space {
+            Some(ref space) => space.games[0].same_puzzle(game) && space.index_of(game).is_some(),
+            None => false
+        };
+
+        if !cached {
+            match explore(game, limits) {
+                Ok(space) => self.space = Some(space),
+                Err(limit) => return SolveResult::LimitExceeded(limit)
+            }
+        }
+
+        let space = self.space.as_ref().unwrap();
+        let index = space.index_of(game).unwrap();
+
+        let distance = match space.distances[index] {
+            Some(distance) => distance,
+            None => return SolveResult::ProvenUnsolvable
+        };
+
+        let mut next_move = None;
+        if distance > 0 {
+            for &(ref piece_move, neighbour) in &space.neighbours[index] {
+                if space.distances[neighbour] == Some(distance - 1) {
+                    next_move = Some(piece_move.clone());
+                    break;
+                }
+            }
+        }
+
+        SolveResult::Solved(Hint {next_move: next_move, distance: distance})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use game::Game;
+    use solver::{Limit, SolveLimits, SolveResult};
+    use solver::fixtures::medium_game;
+    use super::{hint, HintCache};
+
+    #[test]
+    fn following_hints_reaches_the_exit() {
+        let mut game = medium_game();
+        let mut cache = HintCache::new();
+        let limits = SolveLimits::default();
+
+        // Wander off the solution first
+        assert_eq!(game.move_piece(8, true, 1), true);
+
+        let mut distance = hint(&game, &limits).unwrap().distance;
+        while !game.can_exit() {
+            let cached = cache.hint(&game, &limits).unwrap();
+            assert_eq!(cached.distance, distance);
+            assert_eq!(hint(&game, &limits).unwrap().distance, distance);
+
+            assert_eq!(game.apply(&cached.next_move.unwrap()), true);
+            distance -= 1;
+        }
+
+        assert_eq!(distance, 0);
+        assert_eq!(cache.hint(&game, &limits).unwrap().next_move, None);
+    }
+
+    #[test]
+    fn reuses_the_explored_puzzle() {
+        let mut game = medium_game();
+        let mut cache = HintCache::new();
+
+        let first = cache.hint(&game, &SolveLimits::default()).unwrap();
+        assert_eq!(game.apply(&first.next_move.unwrap()), true);
+
+        // Too tight to explore again, so this can only come from the cache
+        let no_room = SolveLimits {max_states: Some(0), ..SolveLimits::default()};
+        assert_eq!(cache.hint(&game, &no_room).unwrap().distance, first.distance - 1);
+
+        let other = Game::array_to_game(vec![
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 2, 0],
+            vec![0, 0, 1, 1, 2, 0],
+            vec![0, 0, 0, 0, 2, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0]
+        ], true, Vec::new(), Vec::new());
+        assert_eq!(cache.hint(&other, &no_room), SolveResult::LimitExceeded(Limit::States));
+
+        // The same layout, but a different puzzle
+        let mut cache = HintCache::new();
+        cache.hint(&medium_game(), &SolveLimits::default()).unwrap();
+        let disabled = Game::array_to_game(medium_game().board.matrix, true, vec![2], Vec::new());
+        assert_eq!(cache.hint(&disabled, &no_room), SolveResult::LimitExceeded(Limit::States));
+        assert_eq!(cache.hint(&disabled, &SolveLimits::default()), hint(&disabled, &SolveLimits::default()));
+    }
+}
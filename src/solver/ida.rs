@@ -0,0 +1,265 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::time::Instant;
+
+use game::{Game, Move, PieceId};
+use super::astar::heuristic;
+use super::{merge_steps, state_key, Limit, Metric, Solution, SolveLimits, SolveResult, SolveStats, Solver};
+
+// Everything a depth first pass needs besides the board it is playing on.
+struct Search<'a> {
+    limits: &'a SolveLimits,
+    metric: Metric,
+    symmetry: bool,
+    // The cheapest cost each state has been reached at during this pass,
+    // holding no more than `table_size` states.
+    table: HashMap<Vec<u8>, usize>,
+    table_size: usize,
+    // The moves from the start to the board being looked at, and the keys of
+    // the states along the way.
+    path: Vec<Move>,
+    path_keys: Vec<Vec<u8>>,
+    // States looked at so far, counted against `SolveLimits::max_states`.
+    visited: usize,
+    // The cheapest estimate that went over this pass's bound.
+    next_bound: Option<usize>,
+    depth_pruned: bool,
+    stats: &'a mut SolveStats
+}
+
+// Same contract as `extra_solve`, in memory that only grows with the length
+// of the solution and `table_size`.
+//
+// Runs depth first passes that give up on any state whose cost so far plus
+// `heuristic` goes over a bound, raising the bound to the cheapest estimate
+// that went over it each time. There is only ever one `Game`, which moves
+// are played on and taken back from. Up to `table_size` states are
+// remembered per pass so paths that meet again are cut short; with a table
+// of zero only loops back onto the current path are.
+pub fn ida_solve(initial_game: &Game, limits: &SolveLimits, metric: Metric, table_size: usize) -> SolveResult<Solution> {
+    ida_solve_with_stats(initial_game, limits, metric, table_size, false).0
+}
+
+// `symmetry` as for `solve_with_stats`.
+pub fn ida_solve_with_stats(initial_game: &Game, limits: &SolveLimits, metric: Metric, table_size: usize, symmetry: bool) -> (SolveResult<Solution>, SolveStats) {
+    let started = Instant::now();
+    let mut stats = SolveStats::default();
+
+    let result = deepen(initial_game, limits, metric, table_size, symmetry, &mut stats);
+    (result, stats.finish(started))
+}
+
+fn deepen(initial_game: &Game, limits: &SolveLimits, metric: Metric, table_size: usize, symmetry: bool, stats: &mut SolveStats) -> SolveResult<Solution> {
+    let mut root = initial_game.clone();
+    root.parent = None;
+    let mut game = root.clone();
+
+    let mut search = Search {
+        limits: limits,
+        metric: metric,
+        symmetry: symmetry,
+        table: HashMap::new(),
+        table_size: table_size,
+        path: Vec::new(),
+        path_keys: Vec::new(),
+        visited: 0,
+        next_bound: None,
+        depth_pruned: false,
+        stats: stats
+    };
+
+    let mut bound = match heuristic(&game, None, metric) {
+        Some(estimate) => estimate,
+        None => return SolveResult::ProvenUnsolvable
+    };
+
+    loop {
+        search.table.clear();
+        search.next_bound = None;
+
+        match search.visit(&mut game, 0, bound) {
+            Ok(true) => return SolveResult::Solved(rebuild(&root, &search.path, metric)),
+            Ok(false) => {},
+            Err(limit) => return SolveResult::LimitExceeded(limit)
+        }
+
+        bound = match search.next_bound {
+            Some(next_bound) if limits.allows_depth(next_bound) => next_bound,
+            Some(_) => return SolveResult::LimitExceeded(Limit::Depth),
+            None if search.depth_pruned => return SolveResult::LimitExceeded(Limit::Depth),
+            None => return SolveResult::ProvenUnsolvable
+        };
+    }
+}
+
+impl<'a> Search<'a> {
+    // Whether an exit can be reached from `game` within `bound`. When it can
+    // `path` ends at it and `game` is left wherever the search got to.
+    fn visit(&mut self, game: &mut Game, cost: usize, bound: usize) -> Result<bool, Limit> {
+        self.visited += 1;
+        if let Some(limit) = self.limits.exceeded(self.visited) {
+            return Err(limit);
+        }
+
+        let last_move = self.path.last().cloned();
+        let estimate = match heuristic(game, last_move.as_ref(), self.metric) {
+            Some(estimate) => estimate,
+            None => return Ok(false)
+        };
+
+        if cost + estimate > bound {
+            self.next_bound = Some(match self.next_bound {
+                Some(next_bound) => cmp::min(next_bound, cost + estimate),
+                None => cost + estimate
+            });
+            return Ok(false);
+        }
+        if game.can_exit() {
+            return Ok(true);
+        }
+
+        let key = state_key(game, last_move.as_ref(), self.metric, self.symmetry);
+        if self.path_keys.contains(&key) {
+            return Ok(false);
+        }
+        match self.table.get(&key) {
+            Some(&known) if known <= cost => return Ok(false),
+            Some(_) => {
+                self.table.insert(key.clone(), cost);
+            },
+            None if self.table.len() < self.table_size => {
+                self.table.insert(key.clone(), cost);
+            },
+            None => {}
+        }
+
+        self.path_keys.push(key);
+        self.stats.reach(cost, self.path_keys.len());
+        self.stats.expanded += 1;
+
+        // The same moves in the same order as `successors`, played in place
+        let max_steps = match self.metric {
+            Metric::Step => 2,
+            _ => cmp::max(game.width, game.height)
+        };
+
+        for index in 0..game.pieces.len() {
+            let piece = PieceId::from_index(index);
+            for &direction in game.directions(piece) {
+                for steps in 1..max_steps {
+                    let piece_move = Move {piece: piece, direction: direction, steps: steps as u8};
+                    if !game.apply(&piece_move) {
+                        break;
+                    }
+                    self.stats.generated += 1;
+
+                    let next_cost = cost + self.metric.cost(last_move.as_ref(), &piece_move);
+                    if !self.limits.allows_depth(next_cost) {
+                        self.depth_pruned = true;
+                    } else {
+                        self.path.push(piece_move.clone());
+                        if self.visit(game, next_cost, bound)? {
+                            return Ok(true);
+                        }
+                        self.path.pop();
+                    }
+
+                    game.apply(&Move {piece: piece, direction: direction.opposite(), steps: steps as u8});
+                }
+            }
+        }
+
+        self.path_keys.pop();
+        Ok(false)
+    }
+}
+
+// Play `moves` from `initial_game` to get the boards along the way.
+fn rebuild(initial_game: &Game, moves: &[Move], metric: Metric) -> Solution {
+    let mut solution = Solution {games: vec![initial_game.clone()], moves: Vec::new()};
+
+    for piece_move in moves {
+        let mut next_game = solution.games.last().unwrap().clone();
+        next_game.apply(piece_move);
+        solution.games.push(next_game);
+        solution.moves.push(piece_move.clone());
+    }
+
+    if metric == Metric::Step {
+        merge_steps(solution)
+    } else {
+        solution
+    }
+}
+
+pub struct Ida {
+    pub table_size: usize,
+    pub symmetry: bool
+}
+
+impl Default for Ida {
+    // A few megabytes of table on a 6x6 board
+    fn default() -> Ida {
+        Ida {table_size: 100000, symmetry: false}
+    }
+}
+
+impl Solver for Ida {
+    fn solve_with_stats(&self, initial_game: &Game, limits: &SolveLimits, metric: Metric) -> (SolveResult<Solution>, SolveStats) {
+        ida_solve_with_stats(initial_game, limits, metric, self.table_size, self.symmetry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use game::Game;
+    use solver::{extra_solve, Limit, Metric, SolveLimits, SolveResult};
+    use solver::fixtures::{easy_game, medium_game};
+    use super::ida_solve;
+
+    #[test]
+    fn finds_solutions_as_short_as_breadth_first() {
+        let game = medium_game();
+
+        for &metric in &[Metric::Slide, Metric::Step, Metric::Piece] {
+            let expected = extra_solve(&game, &SolveLimits::default(), metric).unwrap();
+            let solution = ida_solve(&game, &SolveLimits::default(), metric, 100000).unwrap();
+
+            assert_eq!(solution.cost(metric), expected.cost(metric));
+
+            let mut replayed = game.clone();
+            for piece_move in &solution.moves {
+                assert_eq!(replayed.apply(piece_move), true);
+            }
+            assert_eq!(replayed.can_exit(), true);
+            assert_eq!(&replayed.board.matrix, &solution.games.last().unwrap().board.matrix);
+        }
+    }
+
+    #[test]
+    fn works_without_a_table() {
+        assert_eq!(ida_solve(&easy_game(), &SolveLimits::default(), Metric::Slide, 0).unwrap().moves.len(), 2);
+    }
+
+    #[test]
+    fn reports_unsolvable_games_and_limits() {
+        let blocked = Game::array_to_game(vec![
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![1, 1, 0, 0, 0, 2],
+            vec![0, 0, 0, 0, 0, 2],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+        ], true, vec![2], Vec::new());
+        assert_eq!(ida_solve(&blocked, &SolveLimits::default(), Metric::Slide, 100).map(|_| ()),
+                   SolveResult::ProvenUnsolvable);
+
+        let shallow = SolveLimits {max_depth: Some(2), ..SolveLimits::default()};
+        assert_eq!(ida_solve(&medium_game(), &shallow, Metric::Slide, 100).map(|_| ()),
+                   SolveResult::LimitExceeded(Limit::Depth));
+
+        let few_states = SolveLimits {max_states: Some(10), ..SolveLimits::default()};
+        assert_eq!(ida_solve(&medium_game(), &few_states, Metric::Slide, 100).map(|_| ()),
+                   SolveResult::LimitExceeded(Limit::States));
+    }
+}
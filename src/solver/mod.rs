@@ -1,158 +1,521 @@
 use std::cmp;
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::str::FromStr;
+use std::time::Instant;
+
+use game::{Game, Move, PieceId};
+
+mod astar;
+mod bidirectional;
+mod checkpoint;
+mod count;
+mod disk;
+mod explore;
+#[cfg(test)]
+mod fixtures;
+mod hint;
+mod ida;
+mod parallel;
+mod replay;
+mod shorten;
+pub use self::astar::{astar_solve, AStar};
+pub use self::bidirectional::{bidirectional_solve, Bidirectional};
+pub use self::checkpoint::{resumable_solve, Checkpoint, Progress};
+pub use self::count::{count_solutions, SolutionCount};
+pub use self::disk::{disk_explore, disk_solve, Layer};
+pub use self::explore::{explore, StateSpace};
+pub use self::hint::{hint, Hint, HintCache};
+pub use self::ida::{ida_solve, Ida};
+pub use self::parallel::{parallel_solve, Parallel};
+pub use self::replay::{replay, Replay};
+pub use self::shorten::shorten;
+
+// Bounds on how much work a search may do before giving up. Every limit is
+// optional and the default places none at all.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Default)]
+pub struct SolveLimits {
+    pub max_states: Option<usize>,
+    pub max_depth: Option<usize>,
+    pub deadline: Option<Instant>
+}
 
-use game::{Game, Board};
+impl SolveLimits {
+    fn exceeded(&self, states: usize) -> Option<Limit> {
+        if let Some(max_states) = self.max_states {
+            if states > max_states {
+                return Some(Limit::States);
+            }
+        }
+
+        if let Some(deadline) = self.deadline {
+            if Instant::now() >= deadline {
+                return Some(Limit::Deadline);
+            }
+        }
+
+        None
+    }
+
+    fn allows_depth(&self, depth: usize) -> bool {
+        match self.max_depth {
+            Some(max_depth) => depth <= max_depth,
+            None => true
+        }
+    }
+}
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq)]
+#[derive(Hash)]
+pub enum Limit {
+    States,
+    Depth,
+    Deadline
+}
 
 #[derive(Debug)]
 #[derive(Clone)]
-// #[derive(PartialEq)]
-pub struct Node {
-    pub game: Game,
-    pub last: Option<Box<Node>>
+#[derive(PartialEq)]
+pub enum SolveResult<T> {
+    Solved(T),
+    // The whole reachable state space was searched without finding an exit.
+    ProvenUnsolvable,
+    // The search was cut short, so nothing is known about the puzzle.
+    LimitExceeded(Limit)
 }
 
-pub fn solve(initial_game: &Game) -> Option<Game> {
-    let mut checked: Vec<Board> = Vec::new();
-    let mut queue: VecDeque<Game> = VecDeque::new();
+impl<T> SolveResult<T> {
+    pub fn unwrap(self) -> T {
+        match self {
+            SolveResult::Solved(solution) => solution,
+            SolveResult::ProvenUnsolvable => panic!("called `SolveResult::unwrap()` on an unsolvable game"),
+            SolveResult::LimitExceeded(limit) => panic!("called `SolveResult::unwrap()` after exceeding {:?} limit", limit)
+        }
+    }
+
+    pub fn map<U, F: FnOnce(T) -> U>(self, f: F) -> SolveResult<U> {
+        match self {
+            SolveResult::Solved(solution) => SolveResult::Solved(f(solution)),
+            SolveResult::ProvenUnsolvable => SolveResult::ProvenUnsolvable,
+            SolveResult::LimitExceeded(limit) => SolveResult::LimitExceeded(limit)
+        }
+    }
+}
 
-    let max_steps = cmp::max(initial_game.width, initial_game.height);
+// What a search did on the way to its answer, for tuning limits and the
+// generator.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Default)]
+#[derive(RustcDecodable, RustcEncodable)]
+pub struct SolveStats {
+    // States whose successors were generated.
+    pub expanded: usize,
+    // Successors generated, including ones that had been seen before.
+    pub generated: usize,
+    // Most states waiting in the queue at any one time. For `ida_solve`,
+    // which keeps no queue, the longest path it held instead.
+    pub max_frontier: usize,
+    // Successors generated per state expanded.
+    pub branching_factor: f64,
+    // Highest depth, or cost for the solvers that weigh moves by `Metric`,
+    // of a state taken off the queue.
+    pub depth: usize,
+    pub elapsed_ms: f64
+}
 
-    queue.push_back(initial_game.clone());
+impl SolveStats {
+    fn expand(&mut self, successors: usize) {
+        self.expanded += 1;
+        self.generated += successors;
+    }
 
-    while !queue.is_empty() {
-        // We know for sure there is at least one thing in the queue
-        let game = queue.pop_front().unwrap();
+    fn reach(&mut self, depth: usize, frontier: usize) {
+        self.depth = cmp::max(self.depth, depth);
+        self.max_frontier = cmp::max(self.max_frontier, frontier);
+    }
 
-        if checked.len() > 1000 {
-            return None;
+    fn finish(mut self, started: Instant) -> SolveStats {
+        if self.expanded > 0 {
+            self.branching_factor = self.generated as f64 / self.expanded as f64;
+        }
+        self.elapsed_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+        self
+    }
+}
+
+pub fn solve(initial_game: &Game, limits: &SolveLimits) -> SolveResult<Game> {
+    solve_with_stats(initial_game, limits, false).0
+}
+
+// With `symmetry`, boards with the same `Game::canonical_key` count as one
+// state, which saves work without changing the answer.
+pub fn solve_with_stats(initial_game: &Game, limits: &SolveLimits, symmetry: bool) -> (SolveResult<Game>, SolveStats) {
+    let started = Instant::now();
+    let mut stats = SolveStats::default();
+
+    let result = breadth_first(initial_game, limits, symmetry, &mut stats);
+    (result, stats.finish(started))
+}
+
+fn breadth_first(initial_game: &Game, limits: &SolveLimits, symmetry: bool, stats: &mut SolveStats) -> SolveResult<Game> {
+    let mut checked: HashSet<Vec<u8>> = HashSet::new();
+    let mut queue: VecDeque<(Game, usize)> = VecDeque::new();
+    let mut depth_pruned = false;
+
+    queue.push_back((initial_game.clone(), 0));
+
+    while let Some((game, depth)) = queue.pop_front() {
+        stats.reach(depth, queue.len() + 1);
+        if let Some(limit) = limits.exceeded(checked.len()) {
+            return SolveResult::LimitExceeded(limit);
         }
         if game.can_exit() {
-            return Some(game.clone());
+            return SolveResult::Solved(game.clone());
         }
-        else if game_in(&game, &checked) == false {
-            checked.push(game.board.clone());
-
-            for i in 0..game.pieces.len() {
-                for j in 1..max_steps {
-                    let mut pos_game = game.clone();
-                    if pos_game.move_piece(i as i8, true, j as u8) {
-                        queue.push_back(pos_game);
-                    } else {
-                        break;
-                    }
-                }
+        else if !limits.allows_depth(depth + 1) {
+            depth_pruned = true;
+        }
+        else if checked.insert(state_key(&game, None, Metric::Slide, symmetry)) {
+            let next_games = successors(&game, Metric::Slide);
+            stats.expand(next_games.len());
 
-                for j in 1..max_steps {
-                    let mut neg_game = game.clone();
-                    if neg_game.move_piece(i as i8, false, j as u8) {
-                        queue.push_back(neg_game);
-                    } else {
-                        break;
-                    }
-                }
+            for (_, next_game) in next_games {
+                queue.push_back((next_game, depth + 1));
+            }
+        }
+    }
+
+    exhausted(depth_pruned)
+}
+
+// How the length of a solution is counted.
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+#[derive(RustcDecodable, RustcEncodable)]
+pub enum Metric {
+    // Every `move_piece` call is one move, however far the piece slides.
+    Slide,
+    // Every cell a piece travels is one move.
+    Step,
+    // Moving the same piece several times in a row is one move.
+    Piece
+}
+
+impl Metric {
+    // What it costs to play `piece_move` straight after `last_move`.
+    fn cost(&self, last_move: Option<&Move>, piece_move: &Move) -> usize {
+        match *self {
+            Metric::Slide => 1,
+            Metric::Step => piece_move.steps as usize,
+            Metric::Piece => match last_move {
+                Some(last_move) if last_move.piece == piece_move.piece => 0,
+                _ => 1
             }
         }
     }
+}
+
+impl FromStr for Metric {
+    type Err = String;
 
-    return None;
+    fn from_str(s: &str) -> Result<Metric, String> {
+        match s {
+            "slide" => Ok(Metric::Slide),
+            "step" => Ok(Metric::Step),
+            "piece" => Ok(Metric::Piece),
+            _ => Err(format!("unknown metric: {}", s))
+        }
+    }
 }
 
-pub fn extra_solve(initial_game: &Game) -> Option<Vec<Game>> {
-    let mut nodes: Vec<Node> = Vec::new();
-    let mut checked: Vec<Board> = Vec::new();
-    let mut queue: VecDeque<Node> = VecDeque::new();
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Solution {
+    // Every board from the start to the first one the prisoner can leave.
+    pub games: Vec<Game>,
+    // `moves[i]` turns `games[i]` into `games[i + 1]`.
+    pub moves: Vec<Move>
+}
 
-    let max_steps = cmp::max(initial_game.width, initial_game.height);
+impl Solution {
+    pub fn cost(&self, metric: Metric) -> usize {
+        let mut cost = 0;
+        let mut last_move = None;
 
-    nodes.push(
-        Node {
-            game: initial_game.clone(),
-            last: None
+        for piece_move in &self.moves {
+            cost += metric.cost(last_move, piece_move);
+            last_move = Some(piece_move);
         }
-    );
-    queue.push_back(Node {
-        game: initial_game.clone(),
-        last: None
-    });
-
-    while !queue.is_empty() {
-        // We know for sure there is at least one thing in the queue
-        let game_node = queue.pop_front().unwrap();
-
-        if game_node.game.can_exit() {
-            let mut games = Vec::new();
-
-            let mut solved_option_node = Some(Box::new(game_node.clone()));
-            loop {
-                match solved_option_node {
-                    Some(node) => {
-                        games.push((*node).game.clone());
-                        solved_option_node = node.last;
-                    },
-                    None => {break;}
-                };
-            }
 
-            games.reverse();
+        cost
+    }
+}
+
+// A strategy for finding the cheapest solution to a game. Every one of them
+// gives an optimal solution under `metric`, but not necessarily the same one.
+pub trait Solver {
+    fn solve_with_stats(&self, initial_game: &Game, limits: &SolveLimits, metric: Metric) -> (SolveResult<Solution>, SolveStats);
+
+    fn solve(&self, initial_game: &Game, limits: &SolveLimits, metric: Metric) -> SolveResult<Solution> {
+        self.solve_with_stats(initial_game, limits, metric).0
+    }
+}
+
+// `extra_solve` as a `Solver`.
+#[derive(Default)]
+pub struct BreadthFirst {
+    pub symmetry: bool
+}
+
+impl Solver for BreadthFirst {
+    fn solve_with_stats(&self, initial_game: &Game, limits: &SolveLimits, metric: Metric) -> (SolveResult<Solution>, SolveStats) {
+        extra_solve_with_stats(initial_game, limits, metric, self.symmetry)
+    }
+}
+
+// The built in strategies by the names the command line uses for them, folding
+// mirrored boards together if `symmetry` is set. The bidirectional search
+// can't do that, since a mirrored goal isn't one of its goal states, and
+// `Metric::Piece` never does (see `state_key`).
+pub fn solver_named(name: &str, symmetry: bool) -> Result<Box<dyn Solver>, String> {
+    match name {
+        "bfs" => Ok(Box::new(BreadthFirst {symmetry: symmetry})),
+        "astar" => Ok(Box::new(AStar {symmetry: symmetry})),
+        "bidirectional" if symmetry => Err("the bidirectional solver can't fold mirrored boards together".to_string()),
+        "bidirectional" => Ok(Box::new(Bidirectional)),
+        "parallel" => Ok(Box::new(Parallel {symmetry: symmetry, ..Parallel::new()})),
+        "ida" => Ok(Box::new(Ida {symmetry: symmetry, ..Ida::default()})),
+        _ => Err(format!("unknown solver: {}", name))
+    }
+}
+
+// One entry in `extra_solve`'s arena.
+struct Node {
+    game: Game,
+    last_move: Option<Move>,
+    cost: usize
+}
+
+pub fn extra_solve(initial_game: &Game, limits: &SolveLimits, metric: Metric) -> SolveResult<Solution> {
+    extra_solve_with_stats(initial_game, limits, metric, false).0
+}
+
+// `symmetry` as for `solve_with_stats`.
+pub fn extra_solve_with_stats(initial_game: &Game, limits: &SolveLimits, metric: Metric, symmetry: bool) -> (SolveResult<Solution>, SolveStats) {
+    let started = Instant::now();
+    let mut stats = SolveStats::default();
+
+    let result = cheapest_first(initial_game, limits, metric, symmetry, &mut stats);
+    (result, stats.finish(started))
+}
 
-            return Some(games);
+// Everything `extra_solve` knows part way through a search.
+//
+// Every state we reach is stored once in `arena` and the queue only holds
+// indices into it. `Game::parent` points at the index of the state we came
+// from, so the solution is recovered by walking back to the root.
+struct Frontier {
+    arena: Vec<Node>,
+    best: HashMap<Vec<u8>, usize>,
+    queue: VecDeque<usize>,
+    depth_pruned: bool,
+    // Whether `best` is keyed on `Game::canonical_key`.
+    symmetry: bool
+}
+
+impl Frontier {
+    fn new(initial_game: &Game, metric: Metric, symmetry: bool) -> Frontier {
+        let mut frontier = Frontier {arena: Vec::new(), best: HashMap::new(), queue: VecDeque::new(), depth_pruned: false, symmetry: symmetry};
+
+        let mut root = initial_game.clone();
+        root.parent = None;
+        frontier.best.insert(state_key(&root, None, metric, symmetry), 0);
+        frontier.arena.push(Node {game: root, last_move: None, cost: 0});
+        frontier.queue.push_back(0);
+
+        frontier
+    }
+}
+
+fn cheapest_first(initial_game: &Game, limits: &SolveLimits, metric: Metric, symmetry: bool, stats: &mut SolveStats) -> SolveResult<Solution> {
+    continue_search(&mut Frontier::new(initial_game, metric, symmetry), limits, metric, stats)
+}
+
+// Moves cost either 0 or 1 under every metric (`Metric::Step` only generates
+// single steps), so a deque that takes free moves at the front pops states
+// in order of cost and the first exit we pop is optimal.
+//
+// When the search runs into a limit `frontier` is left exactly as it was, so
+// calling this again with looser limits carries on where it stopped.
+fn continue_search(frontier: &mut Frontier, limits: &SolveLimits, metric: Metric, stats: &mut SolveStats) -> SolveResult<Solution> {
+    let Frontier {ref mut arena, ref mut best, ref mut queue, ref mut depth_pruned, symmetry} = *frontier;
+
+    while let Some(index) = queue.pop_front() {
+        if let Some(limit) = limits.exceeded(best.len()) {
+            queue.push_front(index);
+            return SolveResult::LimitExceeded(limit);
+        }
+
+        let cost = arena[index].cost;
+        stats.reach(cost, queue.len() + 1);
+        if best[&state_key(&arena[index].game, arena[index].last_move.as_ref(), metric, symmetry)] < cost {
+            // Reached again more cheaply after this entry was queued
+            continue;
+        }
+        if arena[index].game.can_exit() {
+            return SolveResult::Solved(unwind(arena, index, metric));
         }
-        else if game_in(&game_node.game, &checked) == false {
-            checked.push(game_node.game.board.clone());
-
-            for i in 0..game_node.game.pieces.len() {
-                for j in 1..max_steps {
-                    let mut pos_game = game_node.game.clone();
-                    if pos_game.move_piece(i as i8, true, j as u8) {
-                        queue.push_back(Node {
-                            game: pos_game.clone(),
-                            last: Some(Box::new(game_node.clone()))
-                        });
-                        nodes.push(Node {
-                            game: pos_game.clone(),
-                            last: Some(Box::new(game_node.clone()))
-                        });
-                    } else {
-                        break;
-                    }
+
+        let next_games = successors(&arena[index].game, metric);
+        stats.expand(next_games.len());
+
+        for (piece_move, mut next_game) in next_games {
+            let move_cost = metric.cost(arena[index].last_move.as_ref(), &piece_move);
+            let next_cost = cost + move_cost;
+            if !limits.allows_depth(next_cost) {
+                *depth_pruned = true;
+                continue;
+            }
+            let key = state_key(&next_game, Some(&piece_move), metric, symmetry);
+
+            let improves = match best.get(&key) {
+                Some(&known) => next_cost < known,
+                None => true
+            };
+
+            if improves {
+                best.insert(key, next_cost);
+                next_game.parent = Some(index);
+                arena.push(Node {game: next_game, last_move: Some(piece_move), cost: next_cost});
+
+                if move_cost == 0 {
+                    queue.push_front(arena.len() - 1);
+                } else {
+                    queue.push_back(arena.len() - 1);
                 }
+            }
+        }
+    }
+
+    exhausted(*depth_pruned)
+}
 
-                for j in 1..max_steps {
-                    let mut neg_game = game_node.game.clone();
-                    if neg_game.move_piece(i as i8, false, j as u8) {
-                        queue.push_back(Node {
-                            game: neg_game.clone(),
-                            last: Some(Box::new(game_node.clone()))
-                        });
-                        nodes.push(Node {
-                            game: neg_game.clone(),
-                            last: Some(Box::new(game_node.clone()))
-                        });
-                    } else {
-                        break;
-                    }
+// Every game one `slide` away, in the order the solvers have always
+// tried them: each piece in turn, forwards then backwards, shortest first.
+// Under `Metric::Step` pieces only ever move a single cell.
+pub fn successors(game: &Game, metric: Metric) -> Vec<(Move, Game)> {
+    let mut next_games = Vec::new();
+
+    let max_steps = match metric {
+        Metric::Step => 2,
+        _ => cmp::max(game.width, game.height)
+    };
+
+    for i in 0..game.pieces.len() {
+        let piece = PieceId::from_index(i);
+        for &direction in game.directions(piece) {
+            for j in 1..max_steps {
+                let piece_move = Move {piece: piece, direction: direction, steps: j as u8};
+
+                let mut next_game = game.clone();
+                if !next_game.apply(&piece_move) {
+                    break;
                 }
+
+                next_games.push((piece_move, next_game));
             }
         }
     }
 
-    return None;
+    next_games
+}
+
+// Under `Metric::Piece` the cost of the next move depends on which piece
+// moved last, so that piece is part of the state. Which piece that is isn't
+// kept when pieces are relabelled, so `symmetry` is ignored for that metric.
+fn state_key(game: &Game, last_move: Option<&Move>, metric: Metric, symmetry: bool) -> Vec<u8> {
+    if symmetry && metric != Metric::Piece {
+        return game.canonical_key();
+    }
+
+    let mut key = game.key();
+
+    if metric == Metric::Piece {
+        key.push(match last_move {
+            Some(last_move) => last_move.piece.0 as u8,
+            None => u8::MAX
+        });
+    }
+
+    key
+}
+
+// What an emptied queue means: if the depth limit stopped us from expanding
+// some states we can't claim the puzzle has no solution.
+fn exhausted<T>(depth_pruned: bool) -> SolveResult<T> {
+    if depth_pruned {
+        SolveResult::LimitExceeded(Limit::Depth)
+    } else {
+        SolveResult::ProvenUnsolvable
+    }
+}
+
+fn unwind(arena: &[Node], index: usize, metric: Metric) -> Solution {
+    let mut solution = Solution {games: Vec::new(), moves: Vec::new()};
+
+    let mut current = Some(index);
+    while let Some(i) = current {
+        solution.games.push(arena[i].game.clone());
+        if let Some(ref piece_move) = arena[i].last_move {
+            solution.moves.push(piece_move.clone());
+        }
+        current = arena[i].game.parent;
+    }
+
+    solution.games.reverse();
+    solution.moves.reverse();
+
+    if metric == Metric::Step {
+        merge_steps(solution)
+    } else {
+        solution
+    }
 }
 
-fn game_in(game: &Game, vec: &Vec<Board>) -> bool {
-  for board in vec {
-    if *game.board.matrix == *board.matrix {
-      return true;
+// Fold runs of single steps by the same piece in the same direction back
+// into one slide, dropping the boards in between.
+fn merge_steps(solution: Solution) -> Solution {
+    let mut merged = Solution {games: vec![solution.games[0].clone()], moves: Vec::new()};
+
+    for (piece_move, game) in solution.moves.into_iter().zip(solution.games.into_iter().skip(1)) {
+        let extends_last = match merged.moves.last() {
+            Some(last_move) => last_move.piece == piece_move.piece && last_move.direction == piece_move.direction,
+            None => false
+        };
+
+        if extends_last {
+            merged.moves.last_mut().unwrap().steps += piece_move.steps;
+            *merged.games.last_mut().unwrap() = game;
+        } else {
+            merged.moves.push(piece_move);
+            merged.games.push(game);
+        }
     }
-  }
 
-  false
+    merged
 }
 
 #[cfg(test)]
 mod tests {
-    use game::Game;
-    use super::solve;
+    use game::{Direction, Game, Move, PieceId, PieceKind};
+    use solver::fixtures::tricky_game;
+    use super::{solve, solve_with_stats, extra_solve, extra_solve_with_stats, shorten, solver_named, Limit, Metric, Solution, SolveLimits, SolveResult, SolveStats, Solver};
 
     #[test]
     fn can_solve_easy_game() {
@@ -165,7 +528,7 @@ mod tests {
             vec![0, 0, 0, 0, 0, 0]
         ], true, Vec::new(), Vec::new());
 
-        let solved_game = solve(&game).unwrap();
+        let solved_game = solve(&game, &SolveLimits::default()).unwrap();
 
         assert_eq!(solved_game.board.matrix, vec![
             vec![0, 0, 0, 0, 0, 0],
@@ -177,6 +540,37 @@ mod tests {
         ]);
     }
 
+    #[test]
+    fn extra_solve_walks_back_to_the_start() {
+        let game = Game::array_to_game(vec![
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 2, 0],
+            vec![0, 0, 1, 1, 2, 3],
+            vec![0, 0, 0, 0, 2, 3],
+            vec![0, 0, 0, 0, 0, 3],
+            vec![0, 0, 0, 0, 0, 0]
+        ], true, Vec::new(), Vec::new());
+
+        let solution = extra_solve(&game, &SolveLimits::default(), Metric::Slide).unwrap();
+        let games = solution.games;
+
+        assert_eq!(games.len(), 3);
+        assert_eq!(games[0].board.matrix, game.board.matrix);
+        assert_eq!(games[0].parent, None);
+        assert_eq!(games[2].can_exit(), true);
+
+        assert_eq!(solution.moves, vec![
+            Move {piece: PieceId(2), direction: Direction::Down, steps: 2},
+            Move {piece: PieceId(3), direction: Direction::Down, steps: 1}
+        ]);
+
+        let mut replayed = game.clone();
+        for piece_move in &solution.moves {
+            assert_eq!(replayed.apply(piece_move), true);
+        }
+        assert_eq!(replayed.board.matrix, games[2].board.matrix);
+    }
+
     #[test]
     fn can_not_solve_game_with_reversed_piece() {
         let game = Game::array_to_game(vec![
@@ -188,7 +582,7 @@ mod tests {
             vec![0, 0, 0, 0, 0, 0],
         ], true, Vec::new(), vec![2]);
 
-        assert_eq!(solve(&game).is_none(), true);
+        assert_eq!(solve(&game, &SolveLimits::default()).map(|_| ()), SolveResult::ProvenUnsolvable);
     }
 
     #[test]
@@ -202,7 +596,7 @@ mod tests {
             vec![0, 0, 0, 0, 0, 0],
         ], true, Vec::new(), vec![2]);
 
-        let solved_game = solve(&game).unwrap();
+        let solved_game = solve(&game, &SolveLimits::default()).unwrap();
 
         assert_eq!(solved_game.board.matrix, vec![
             vec![0, 0, 0, 0, 0, 0],
@@ -225,7 +619,7 @@ mod tests {
             vec![0, 0, 0, 0, 0, 0],
         ], true, vec![2], Vec::new());
 
-        assert_eq!(solve(&game).is_none(), true);
+        assert_eq!(solve(&game, &SolveLimits::default()).map(|_| ()), SolveResult::ProvenUnsolvable);
     }
 
     #[test]
@@ -239,7 +633,7 @@ mod tests {
             vec![0, 0, 0, 0, 0, 0],
         ], true, vec![3, 4], Vec::new());
 
-        let solved_game = solve(&game).unwrap();
+        let solved_game = solve(&game, &SolveLimits::default()).unwrap();
 
         assert_eq!(solved_game.board.matrix, vec![
             vec![0, 0, 0, 0, 4, 0],
@@ -262,7 +656,7 @@ mod tests {
             vec![7, 7, 8, 9, 9, 0]
         ], true, Vec::new(), Vec::new());
 
-        let solved_game = solve(&game).unwrap();
+        let solved_game = solve(&game, &SolveLimits::default()).unwrap();
 
         assert_eq!(solved_game.board.matrix, vec![
             vec![0, 0, 8, 4, 0, 5],
@@ -276,16 +670,9 @@ mod tests {
 
     #[test]
     fn can_solve_tricky_game() {
-        let game = Game::array_to_game(vec![
-            vec![2, 3, 3, 0, 5, 6],
-            vec![2, 0, 4, 0, 5, 6],
-            vec![0, 0, 4, 1, 1, 7],
-            vec![11, 11, 10, 9, 0, 7],
-            vec![0, 0, 10, 9, 8, 8],
-            vec![12, 12, 12, 9, 0, 0]
-        ], true, Vec::new(), Vec::new());
+        let game = tricky_game();
 
-        let solved_game = solve(&game).unwrap();
+        let solved_game = solve(&game, &SolveLimits::default()).unwrap();
 
         assert_eq!(solved_game.board.matrix, vec![
             vec![2, 0, 4, 3, 3, 6],
@@ -296,4 +683,302 @@ mod tests {
             vec![12, 12, 12, 9, 0, 0]
         ]);
     }
+
+    #[test]
+    fn reports_which_limit_was_hit() {
+        let game = tricky_game();
+
+        let few_states = SolveLimits {max_states: Some(10), ..SolveLimits::default()};
+        assert_eq!(solve(&game, &few_states).map(|_| ()), SolveResult::LimitExceeded(Limit::States));
+        assert_eq!(extra_solve(&game, &few_states, Metric::Slide).map(|_| ()), SolveResult::LimitExceeded(Limit::States));
+
+        let shallow = SolveLimits {max_depth: Some(2), ..SolveLimits::default()};
+        assert_eq!(solve(&game, &shallow).map(|_| ()), SolveResult::LimitExceeded(Limit::Depth));
+        assert_eq!(extra_solve(&game, &shallow, Metric::Slide).map(|_| ()), SolveResult::LimitExceeded(Limit::Depth));
+    }
+
+    #[test]
+    fn reports_what_the_search_did() {
+        let game = tricky_game();
+
+        let (result, stats) = extra_solve_with_stats(&game, &SolveLimits::default(), Metric::Slide, false);
+        let solution = result.unwrap();
+
+        assert_eq!(stats.depth, solution.moves.len());
+        assert!(stats.expanded > 0);
+        assert!(stats.generated >= stats.expanded);
+        assert!(stats.max_frontier > 0);
+        assert_eq!(stats.branching_factor, stats.generated as f64 / stats.expanded as f64);
+
+        let (result, stats) = solve_with_stats(&game, &SolveLimits::default(), false);
+        assert_eq!(result.map(|_| ()), SolveResult::Solved(()));
+        assert_eq!(stats.depth, solution.moves.len());
+
+        let (_, stats) = extra_solve_with_stats(&game, &SolveLimits {max_depth: Some(0), ..SolveLimits::default()}, Metric::Slide, false);
+        assert_eq!(stats.expanded, 1);
+        assert_eq!(stats.depth, 0);
+    }
+
+    #[test]
+    fn every_solver_finds_an_optimal_solution() {
+        let game = Game::array_to_game(vec![
+            vec![2, 0, 0, 4, 0, 5],
+            vec![2, 3, 3, 4, 0, 5],
+            vec![2, 0, 1, 1, 10, 0],
+            vec![6, 6, 6, 0, 10, 11],
+            vec![0, 0, 8, 0, 10, 11],
+            vec![7, 7, 8, 9, 9, 0]
+        ], true, Vec::new(), Vec::new());
+        let expected = extra_solve(&game, &SolveLimits::default(), Metric::Slide).unwrap();
+
+        for name in &["bfs", "astar", "bidirectional", "parallel", "ida"] {
+            let solver = solver_named(name, false).unwrap();
+            let (result, stats) = solver.solve_with_stats(&game, &SolveLimits::default(), Metric::Slide);
+
+            assert_eq!(result.unwrap().moves.len(), expected.moves.len());
+            assert!(stats.expanded > 0);
+            assert!(stats.generated >= stats.expanded);
+            assert!(stats.max_frontier > 0);
+            assert!(stats.elapsed_ms >= 0.0);
+        }
+
+        assert!(solver_named("guess", false).is_err());
+    }
+
+    #[test]
+    fn every_solver_handles_blocks_and_long_bars() {
+        // A two by two prisoner, a block that slides up and down, and a
+        // board taller than it is wide
+        let game = Game::array_to_game(vec![
+            vec![0, 0, 0, 2, 0, 0],
+            vec![0, 0, 0, 2, 0, 0],
+            vec![1, 1, 0, 2, 3, 3],
+            vec![1, 1, 0, 0, 3, 3],
+            vec![0, 0, 0, 4, 4, 4],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![5, 5, 5, 0, 0, 0]
+        ], true, Vec::new(), vec![3]);
+
+        for name in &["bfs", "astar", "bidirectional", "parallel", "ida"] {
+            let solution = solver_named(name, false).unwrap().solve(&game, &SolveLimits::default(), Metric::Slide).unwrap();
+
+            assert_eq!(solution.moves.len(), 3);
+            assert_eq!(solution.games.last().unwrap().can_exit(), true);
+        }
+    }
+
+    #[test]
+    fn every_solver_moves_free_pieces_both_ways() {
+        let mut game = Game::array_to_game(vec![
+            vec![0, 0, 3, 3, 3, 3],
+            vec![0, 0, 0, 2, 2, 0],
+            vec![1, 1, 0, 2, 2, 0],
+            vec![0, 0, 0, 0, 4, 0],
+            vec![0, 0, 0, 0, 4, 0],
+            vec![0, 0, 0, 0, 0, 0]
+        ], true, vec![3, 4], Vec::new());
+        assert_eq!(extra_solve(&game, &SolveLimits::default(), Metric::Slide).map(|_| ()), SolveResult::ProvenUnsolvable);
+
+        game.pieces[1].kind = PieceKind::Free;
+        let around = vec![
+            Move {piece: PieceId(2), direction: Direction::Left, steps: 1},
+            Move {piece: PieceId(2), direction: Direction::Down, steps: 2}
+        ];
+
+        for name in &["bfs", "astar", "bidirectional", "parallel", "ida"] {
+            let solver = solver_named(name, false).unwrap();
+            assert_eq!(solver.solve(&game, &SolveLimits::default(), Metric::Slide).unwrap().moves, around);
+            assert_eq!(solver.solve(&game, &SolveLimits::default(), Metric::Step).unwrap().cost(Metric::Step), 3);
+        }
+
+        let wandering = vec![
+            Move {piece: PieceId(2), direction: Direction::Left, steps: 1},
+            Move {piece: PieceId(2), direction: Direction::Down, steps: 1},
+            Move {piece: PieceId(2), direction: Direction::Down, steps: 1}
+        ];
+        assert_eq!(shorten(&game, &wandering).unwrap(), around);
+
+        // A free prisoner steps out of a walled off row instead
+        let mut walled = Game::array_to_game(vec![
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![1, 1, 0, -1, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0]
+        ], true, Vec::new(), Vec::new());
+        assert_eq!(extra_solve(&walled, &SolveLimits::default(), Metric::Slide).map(|_| ()), SolveResult::ProvenUnsolvable);
+
+        walled.pieces[0].kind = PieceKind::Free;
+        for name in &["bfs", "astar", "bidirectional", "parallel", "ida"] {
+            let solver = solver_named(name, false).unwrap();
+            for &metric in &[Metric::Slide, Metric::Step, Metric::Piece] {
+                assert_eq!(solver.solve(&walled, &SolveLimits::default(), metric).unwrap().cost(metric), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn every_solver_respects_walls() {
+        let mut game = Game::array_to_game(vec![
+            vec![0, 0, 0, -1, 0, 0],
+            vec![0, 0, 0, 2, 0, 0],
+            vec![1, 1, 0, 2, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, -1, 0, 0],
+            vec![0, 0, 0, 0, 0, 0]
+        ], true, Vec::new(), Vec::new());
+
+        for name in &["bfs", "astar", "bidirectional", "parallel", "ida"] {
+            let result = solver_named(name, false).unwrap().solve(&game, &SolveLimits::default(), Metric::Slide);
+            assert_eq!(result.map(|_| ()), SolveResult::ProvenUnsolvable);
+        }
+
+        // Free to step around the walls instead
+        game.pieces[1].kind = PieceKind::Free;
+
+        for name in &["bfs", "astar", "bidirectional", "parallel", "ida"] {
+            let solution = solver_named(name, false).unwrap().solve(&game, &SolveLimits::default(), Metric::Slide).unwrap();
+            assert_eq!(solution.moves.len(), 2);
+
+            let mut replayed = game.clone();
+            for piece_move in &solution.moves {
+                assert_eq!(replayed.apply(piece_move), true);
+            }
+            assert_eq!(replayed.can_exit(), true);
+            assert_eq!(replayed.walls(), game.walls());
+        }
+    }
+
+    #[test]
+    fn runs_a_caller_supplied_solver() {
+        // Gives up straight away, standing in for an experimental strategy
+        struct Impatient;
+
+        impl Solver for Impatient {
+            fn solve_with_stats(&self, _: &Game, _: &SolveLimits, _: Metric) -> (SolveResult<Solution>, SolveStats) {
+                (SolveResult::LimitExceeded(Limit::Deadline), SolveStats::default())
+            }
+        }
+
+        let solvers: Vec<Box<dyn Solver>> = vec![Box::new(Impatient)];
+        let game = Game::array_to_game(vec![
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![1, 1, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0]
+        ], true, Vec::new(), Vec::new());
+
+        assert_eq!(solvers[0].solve(&game, &SolveLimits::default(), Metric::Slide).map(|_| ()),
+                   SolveResult::LimitExceeded(Limit::Deadline));
+    }
+
+    #[test]
+    fn symmetry_saves_work_without_changing_the_answer() {
+        // The prisoner's row is the middle one, so boards mirrored top to
+        // bottom play out the same way
+        let game = Game::array_to_game(vec![
+            vec![0, 0, 0, 2, 0, 0, 0],
+            vec![0, 0, 0, 2, 0, 3, 0],
+            vec![0, 0, 0, 0, 0, 3, 0],
+            vec![1, 1, 0, 4, 0, 3, 0],
+            vec![0, 0, 0, 4, 0, 0, 0],
+            vec![0, 0, 0, 4, 0, 5, 5],
+            vec![6, 6, 0, 0, 0, 0, 0]
+        ], true, Vec::new(), Vec::new());
+
+        for &metric in &[Metric::Slide, Metric::Step] {
+            let plain = extra_solve(&game, &SolveLimits::default(), metric).unwrap();
+
+            for name in &["bfs", "astar", "parallel", "ida"] {
+                let folded = solver_named(name, true).unwrap().solve(&game, &SolveLimits::default(), metric).unwrap();
+
+                assert_eq!(folded.cost(metric), plain.cost(metric));
+
+                let mut replayed = game.clone();
+                for piece_move in &folded.moves {
+                    assert_eq!(replayed.apply(piece_move), true);
+                }
+                assert_eq!(replayed.can_exit(), true);
+            }
+        }
+        assert!(solver_named("bidirectional", true).is_err());
+
+        // Searching everything shows the saving best. Both vertical pieces can
+        // slide to their own mirror images and the horizontal ones mirror each
+        // other.
+        let stuck = Game::array_to_game(vec![
+            vec![0, 0, 0, 2, 0, 0, 0],
+            vec![4, 4, 0, 2, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0, 3],
+            vec![1, 1, 0, 0, 0, 0, 3],
+            vec![0, 0, 0, 0, 0, 0, 3],
+            vec![5, 5, 0, 0, 6, 0, 0],
+            vec![0, 0, 0, 0, 6, 0, 0]
+        ], true, vec![3], Vec::new());
+
+        let (plain, plain_stats) = extra_solve_with_stats(&stuck, &SolveLimits::default(), Metric::Slide, false);
+        let (folded, folded_stats) = extra_solve_with_stats(&stuck, &SolveLimits::default(), Metric::Slide, true);
+        assert_eq!(plain.map(|_| ()), SolveResult::ProvenUnsolvable);
+        assert_eq!(folded.map(|_| ()), SolveResult::ProvenUnsolvable);
+        assert!(folded_stats.expanded < plain_stats.expanded);
+
+        let (_, plain_stats) = solve_with_stats(&stuck, &SolveLimits::default(), false);
+        let (_, folded_stats) = solve_with_stats(&stuck, &SolveLimits::default(), true);
+        assert!(folded_stats.expanded < plain_stats.expanded);
+    }
+
+    #[test]
+    fn optimises_for_the_chosen_metric() {
+        // Piece 2 can clear the way with one long slide, or step down once
+        // after piece 3 steps out of its way.
+        let game = Game::array_to_game(vec![
+            vec![0, 0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0, 0],
+            vec![1, 1, 0, 2, 0, 0, 0],
+            vec![0, 0, 0, 2, 0, 0, 0],
+            vec![0, 0, 0, 2, 0, 0, 0],
+            vec![0, 0, 0, 3, 3, 0, 0]
+        ], true, Vec::new(), Vec::new());
+
+        let slides = extra_solve(&game, &SolveLimits::default(), Metric::Slide).unwrap();
+        assert_eq!(slides.moves, vec![Move {piece: PieceId(2), direction: Direction::Up, steps: 3}]);
+        assert_eq!(slides.cost(Metric::Slide), 1);
+        assert_eq!(slides.cost(Metric::Step), 3);
+
+        let steps = extra_solve(&game, &SolveLimits::default(), Metric::Step).unwrap();
+        assert_eq!(steps.moves, vec![
+            Move {piece: PieceId(3), direction: Direction::Right, steps: 1},
+            Move {piece: PieceId(2), direction: Direction::Down, steps: 1}
+        ]);
+        assert_eq!(steps.games.len(), 3);
+        assert_eq!(steps.cost(Metric::Slide), 2);
+        assert_eq!(steps.cost(Metric::Step), 2);
+
+        let pieces = extra_solve(&game, &SolveLimits::default(), Metric::Piece).unwrap();
+        assert_eq!(pieces.cost(Metric::Piece), 1);
+    }
+
+    #[test]
+    fn piece_metric_counts_runs_of_the_same_piece() {
+        let game = Game::array_to_game(vec![
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 2, 0],
+            vec![0, 0, 1, 1, 2, 3],
+            vec![0, 0, 0, 0, 2, 3],
+            vec![0, 0, 0, 0, 0, 3],
+            vec![0, 0, 0, 0, 0, 0]
+        ], true, Vec::new(), Vec::new());
+
+        let mut solution = extra_solve(&game, &SolveLimits::default(), Metric::Slide).unwrap();
+        solution.moves.insert(1, Move {piece: PieceId(2), direction: Direction::Up, steps: 1});
+        solution.moves.insert(1, Move {piece: PieceId(2), direction: Direction::Down, steps: 1});
+
+        assert_eq!(solution.cost(Metric::Slide), 4);
+        assert_eq!(solution.cost(Metric::Step), 5);
+        assert_eq!(solution.cost(Metric::Piece), 2);
+    }
 }
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+use game::{Game, Move};
+use super::{exhausted, extra_solve_with_stats, state_key, successors, unwind, Metric, Node, Solution, SolveLimits, SolveResult, SolveStats, Solver};
+
+// Where a state was first found: the arena index of the state it was reached
+// from and which of that state's successors it was. Arena indices grow in the
+// order `extra_solve` pops states, so the smallest rank is the discovery
+// `extra_solve` would have kept.
+type Rank = (usize, usize);
+
+// Every state seen so far and the best rank it was reached with, split over
+// several locks so threads rarely wait on each other.
+struct Visited {
+    shards: Vec<Mutex<HashMap<Vec<u8>, Rank>>>
+}
+
+impl Visited {
+    fn new(shards: usize) -> Visited {
+        Visited {shards: (0..shards).map(|_| Mutex::new(HashMap::new())).collect()}
+    }
+
+    fn shard(&self, key: &[u8]) -> &Mutex<HashMap<Vec<u8>, Rank>> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % self.shards.len()]
+    }
+
+    // Record `rank` for `key` unless it was already reached with a smaller
+    // one. Returns whether `rank` is now the best.
+    fn offer(&self, key: &[u8], rank: Rank) -> bool {
+        let mut shard = self.shard(key).lock().unwrap();
+
+        match shard.get(key) {
+            Some(&known) if known <= rank => return false,
+            _ => {}
+        }
+
+        shard.insert(key.to_vec(), rank);
+        true
+    }
+
+    fn is_best(&self, key: &[u8], rank: Rank) -> bool {
+        self.shard(key).lock().unwrap()[key] == rank
+    }
+
+    fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.lock().unwrap().len()).sum()
+    }
+}
+
+// A state found while expanding a level, before it has a place in the arena.
+struct Found {
+    rank: Rank,
+    piece_move: Move,
+    game: Game,
+    key: Vec<u8>
+}
+
+// Same contract and the same solutions as `extra_solve`, spreading each level
+// of the breadth first search over `threads` threads. Limits are checked once
+// per level rather than once per state.
+//
+// `Metric::Piece` has moves that cost nothing, so states can't be split into
+// levels by cost and that metric falls back to `extra_solve`.
+pub fn parallel_solve(initial_game: &Game, limits: &SolveLimits, metric: Metric, threads: usize) -> SolveResult<Solution> {
+    parallel_solve_with_stats(initial_game, limits, metric, threads, false).0
+}
+
+// `symmetry` as for `solve_with_stats`.
+pub fn parallel_solve_with_stats(initial_game: &Game, limits: &SolveLimits, metric: Metric, threads: usize, symmetry: bool) -> (SolveResult<Solution>, SolveStats) {
+    if metric == Metric::Piece {
+        return extra_solve_with_stats(initial_game, limits, metric, symmetry);
+    }
+
+    let started = Instant::now();
+    let mut stats = SolveStats::default();
+
+    let result = by_level(initial_game, limits, metric, threads, symmetry, &mut stats);
+    (result, stats.finish(started))
+}
+
+fn by_level(initial_game: &Game, limits: &SolveLimits, metric: Metric, threads: usize, symmetry: bool, stats: &mut SolveStats) -> SolveResult<Solution> {
+    let threads = if threads == 0 { 1 } else { threads };
+    let visited = Visited::new(threads * 16);
+    let mut arena: Vec<Node> = Vec::new();
+    let mut depth_pruned = false;
+
+    let mut root = initial_game.clone();
+    root.parent = None;
+    visited.offer(&state_key(&root, None, metric, symmetry), (0, 0));
+    arena.push(Node {game: root, last_move: None, cost: 0});
+
+    let mut level: Vec<usize> = vec![0];
+    let mut cost = 0;
+
+    while !level.is_empty() {
+        stats.reach(cost, level.len());
+        if let Some(limit) = limits.exceeded(visited.len()) {
+            return SolveResult::LimitExceeded(limit);
+        }
+
+        // `extra_solve` pops a whole level before the next, in this order
+        if let Some(&goal) = level.iter().find(|&&index| arena[index].game.can_exit()) {
+            return SolveResult::Solved(unwind(&arena, goal, metric));
+        }
+
+        if !limits.allows_depth(cost + 1) {
+            depth_pruned = true;
+            break;
+        }
+
+        let chunk_size = level.len().div_ceil(threads);
+        // What each thread found, and how many successors it generated
+        let found: Vec<(Vec<Found>, usize)> = thread::scope(|scope| {
+            let handles: Vec<_> = level.chunks(chunk_size).map(|chunk| {
+                let arena = &arena;
+                let visited = &visited;
+
+                scope.spawn(move || {
+                    let mut found = Vec::new();
+                    let mut generated = 0;
+
+                    for &index in chunk {
+                        let next_games = successors(&arena[index].game, metric);
+                        generated += next_games.len();
+
+                        for (i, (piece_move, game)) in next_games.into_iter().enumerate() {
+                            // The root holds rank (0, 0), so successors count from 1
+                            let rank = (index, i + 1);
+                            let key = state_key(&game, None, metric, symmetry);
+
+                            if visited.offer(&key, rank) {
+                                found.push(Found {rank: rank, piece_move: piece_move, game: game, key: key});
+                            }
+                        }
+                    }
+
+                    (found, generated)
+                })
+            }).collect();
+
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+        });
+
+        // A state offered by several threads is kept only by the one whose
+        // rank won, and chunks are in level order, so the arena fills up
+        // exactly as `extra_solve`'s would.
+        stats.expanded += level.len();
+        stats.generated += found.iter().map(|&(_, generated)| generated).sum::<usize>();
+
+        let mut next_level = Vec::new();
+        for found in found.into_iter().flat_map(|(found, _)| found.into_iter()) {
+            if !visited.is_best(&found.key, found.rank) {
+                continue;
+            }
+
+            let mut game = found.game;
+            game.parent = Some(found.rank.0);
+            arena.push(Node {game: game, last_move: Some(found.piece_move), cost: cost + 1});
+            next_level.push(arena.len() - 1);
+        }
+
+        level = next_level;
+        cost += 1;
+    }
+
+    exhausted(depth_pruned)
+}
+
+pub struct Parallel {
+    pub threads: usize,
+    pub symmetry: bool
+}
+
+impl Parallel {
+    // One thread for every core the machine reports.
+    pub fn new() -> Parallel {
+        let threads = match thread::available_parallelism() {
+            Ok(threads) => threads.get(),
+            Err(_) => 1
+        };
+
+        Parallel {threads: threads, symmetry: false}
+    }
+}
+
+impl Default for Parallel {
+    fn default() -> Parallel {
+        Parallel::new()
+    }
+}
+
+impl Solver for Parallel {
+    fn solve_with_stats(&self, initial_game: &Game, limits: &SolveLimits, metric: Metric) -> (SolveResult<Solution>, SolveStats) {
+        parallel_solve_with_stats(initial_game, limits, metric, self.threads, self.symmetry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use game::Game;
+    use solver::{extra_solve, Limit, Metric, SolveLimits, SolveResult};
+    use solver::fixtures::tricky_game;
+    use super::parallel_solve;
+
+    #[test]
+    fn finds_the_same_solution_as_extra_solve() {
+        let game = tricky_game();
+
+        for &metric in &[Metric::Slide, Metric::Step, Metric::Piece] {
+            let expected = extra_solve(&game, &SolveLimits::default(), metric).unwrap();
+
+            for &threads in &[1, 4] {
+                let solution = parallel_solve(&game, &SolveLimits::default(), metric, threads).unwrap();
+
+                assert_eq!(solution.moves, expected.moves);
+                assert_eq!(solution.games.len(), expected.games.len());
+                for (game, expected) in solution.games.iter().zip(expected.games.iter()) {
+                    assert_eq!(game.board.matrix, expected.board.matrix);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn agrees_on_unsolvable_and_limited_games() {
+        let blocked = Game::array_to_game(vec![
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![1, 1, 0, 0, 0, 2],
+            vec![0, 0, 0, 0, 0, 2],
+            vec![0, 0, 0, 0, 0, 0],
+            vec![0, 0, 0, 0, 0, 0],
+        ], true, vec![2], Vec::new());
+
+        assert_eq!(parallel_solve(&blocked, &SolveLimits::default(), Metric::Slide, 4).map(|_| ()),
+                   SolveResult::ProvenUnsolvable);
+
+        let shallow = SolveLimits {max_depth: Some(2), ..SolveLimits::default()};
+        assert_eq!(parallel_solve(&tricky_game(), &shallow, Metric::Slide, 4).map(|_| ()),
+                   SolveResult::LimitExceeded(Limit::Depth));
+    }
+}
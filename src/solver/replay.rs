@@ -0,0 +1,138 @@
+
+use game::{Game, Move};
+use super::{extra_solve, Metric, SolveLimits, SolveResult};
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct Replay {
+    // Index of the first move `Game::move_piece` refused, if any. Nothing
+    // after it is replayed.
+    pub first_illegal: Option<usize>,
+    // Whether the prisoner can leave once every move has been played.
+    pub solved: bool,
+    // What the legal moves cost under the metric asked for.
+    pub cost: usize,
+    // What the best solution from the same start costs, when the solver
+    // could find one within its limits.
+    pub optimal: Option<usize>
+}
+
+impl Replay {
+    pub fn legal(&self) -> bool {
+        self.first_illegal.is_none()
+    }
+
+    // How much longer than necessary a valid solution was.
+    pub fn excess(&self) -> Option<usize> {
+        if !self.legal() || !self.solved {
+            return None;
+        }
+
+        self.optimal.map(|optimal| self.cost - optimal)
+    }
+}
+
+// Play `moves` from `initial_game`, checking each one is allowed, and
+// compare the result with an optimal solution.
+pub fn replay(initial_game: &Game, moves: &[Move], limits: &SolveLimits, metric: Metric) -> Replay {
+    let mut game = initial_game.clone();
+    let mut first_illegal = None;
+    let mut cost = 0;
+    let mut last_move: Option<&Move> = None;
+
+    for (i, piece_move) in moves.iter().enumerate() {
+        if !game.apply(piece_move) {
+            first_illegal = Some(i);
+            break;
+        }
+
+        cost += metric.cost(last_move, piece_move);
+        last_move = Some(piece_move);
+    }
+
+    let optimal = match extra_solve(initial_game, limits, metric) {
+        SolveResult::Solved(solution) => Some(solution.cost(metric)),
+        _ => None
+    };
+
+    Replay {
+        first_illegal: first_illegal,
+        solved: first_illegal.is_none() && game.can_exit(),
+        cost: cost,
+        optimal: optimal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use game::{Direction, Move, PieceId};
+    use solver::{Metric, SolveLimits};
+    use solver::fixtures::easy_game;
+    use super::replay;
+
+    #[test]
+    fn accepts_an_optimal_solution() {
+        let moves = vec![
+            Move {piece: PieceId(3), direction: Direction::Down, steps: 1},
+            Move {piece: PieceId(2), direction: Direction::Down, steps: 2}
+        ];
+
+        let result = replay(&easy_game(), &moves, &SolveLimits::default(), Metric::Slide);
+
+        assert_eq!(result.legal(), true);
+        assert_eq!(result.solved, true);
+        assert_eq!(result.cost, 2);
+        assert_eq!(result.optimal, Some(2));
+        assert_eq!(result.excess(), Some(0));
+    }
+
+    #[test]
+    fn measures_a_long_way_round() {
+        let moves = vec![
+            Move {piece: PieceId(2), direction: Direction::Down, steps: 1},
+            Move {piece: PieceId(2), direction: Direction::Down, steps: 1},
+            Move {piece: PieceId(3), direction: Direction::Down, steps: 1}
+        ];
+
+        let result = replay(&easy_game(), &moves, &SolveLimits::default(), Metric::Slide);
+
+        assert_eq!(result.solved, true);
+        assert_eq!(result.excess(), Some(1));
+
+        let result = replay(&easy_game(), &moves, &SolveLimits::default(), Metric::Step);
+        assert_eq!(result.excess(), Some(0));
+    }
+
+    #[test]
+    fn stops_at_the_first_illegal_move() {
+        let moves = vec![
+            Move {piece: PieceId(3), direction: Direction::Down, steps: 1},
+            Move {piece: PieceId(1), direction: Direction::Right, steps: 1},
+            Move {piece: PieceId(2), direction: Direction::Down, steps: 2}
+        ];
+
+        let result = replay(&easy_game(), &moves, &SolveLimits::default(), Metric::Slide);
+
+        assert_eq!(result.first_illegal, Some(1));
+        assert_eq!(result.solved, false);
+        assert_eq!(result.cost, 1);
+        assert_eq!(result.excess(), None);
+
+        for nonsense in &[Move {piece: PieceId(10), direction: Direction::Down, steps: 1}, Move {piece: PieceId(2), direction: Direction::Down, steps: 0},
+                          Move {piece: PieceId(2), direction: Direction::Down, steps: 255}] {
+            let result = replay(&easy_game(), &[nonsense.clone()], &SolveLimits::default(), Metric::Slide);
+            assert_eq!(result.first_illegal, Some(0));
+        }
+    }
+
+    #[test]
+    fn notices_when_the_exit_is_still_blocked() {
+        let moves = vec![Move {piece: PieceId(3), direction: Direction::Down, steps: 1}];
+
+        let result = replay(&easy_game(), &moves, &SolveLimits::default(), Metric::Slide);
+
+        assert_eq!(result.legal(), true);
+        assert_eq!(result.solved, false);
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+
+use game::{Direction, Game, Move, PieceId};
+
+// A sequence no longer than `moves` that ends the same way: at an exit if
+// `moves` ever reaches one, otherwise on the same board. Returns `None` if
+// `moves` can't be played from `initial_game`.
+//
+// Loops back to an earlier board are cut out, runs of moves by one piece
+// become a single slide, and in a solution every move of a piece is dropped
+// if the rest still solves it. That repeats until nothing changes, since
+// each step can open up the others.
+pub fn shorten(initial_game: &Game, moves: &[Move]) -> Option<Vec<Move>> {
+    let mut moves = moves.to_vec();
+
+    let games = play(initial_game, &moves)?;
+    if let Some(exit) = games.iter().position(|game| game.can_exit()) {
+        moves.truncate(exit);
+    }
+    let solves = games.iter().any(|game| game.can_exit());
+
+    loop {
+        let before = moves.len();
+
+        moves = cut_loops(initial_game, &moves);
+        moves = merge_runs(&moves);
+        if solves {
+            moves = drop_idle_pieces(initial_game, &moves);
+        }
+
+        if moves.len() == before {
+            return Some(moves);
+        }
+    }
+}
+
+// Every board from `initial_game` on, or `None` at the first illegal move.
+fn play(initial_game: &Game, moves: &[Move]) -> Option<Vec<Game>> {
+    let mut games = vec![initial_game.clone()];
+
+    for piece_move in moves {
+        let mut next_game = games.last().unwrap().clone();
+        if !next_game.apply(piece_move) {
+            return None;
+        }
+        games.push(next_game);
+    }
+
+    Some(games)
+}
+
+// Whenever a board comes round again, skip straight to its last visit.
+fn cut_loops(initial_game: &Game, moves: &[Move]) -> Vec<Move> {
+    let games = play(initial_game, moves).unwrap();
+
+    let mut last_seen: HashMap<Vec<u8>, usize> = HashMap::new();
+    for (i, game) in games.iter().enumerate() {
+        last_seen.insert(game.key(), i);
+    }
+
+    let mut kept = Vec::new();
+    let mut i = last_seen[&games[0].key()];
+    while i < moves.len() {
+        kept.push(moves[i].clone());
+        i = last_seen[&games[i + 1].key()];
+    }
+
+    kept
+}
+
+// Consecutive moves of one piece along the same line add up to one slide,
+// or to nothing if they cancel out.
+fn merge_runs(moves: &[Move]) -> Vec<Move> {
+    let mut merged: Vec<Move> = Vec::new();
+    let mut offset: i32 = 0;
+
+    for (i, piece_move) in moves.iter().enumerate() {
+        offset += if piece_move.direction.is_forwards() { piece_move.steps as i32 } else { -(piece_move.steps as i32) };
+
+        let run_ends = match moves.get(i + 1) {
+            Some(next_move) => next_move.piece != piece_move.piece ||
+                next_move.direction.is_horizontal() != piece_move.direction.is_horizontal(),
+            None => true
+        };
+
+        if run_ends {
+            if offset != 0 {
+                merged.push(Move {piece: piece_move.piece, direction: Direction::along(piece_move.direction.is_horizontal(), offset > 0), steps: offset.unsigned_abs() as u8});
+            }
+            offset = 0;
+        }
+    }
+
+    merged
+}
+
+// Leave out everything a piece does if the prisoner gets out without it.
+fn drop_idle_pieces(initial_game: &Game, moves: &[Move]) -> Vec<Move> {
+    let mut moves = moves.to_vec();
+
+    for index in 0..initial_game.pieces.len() {
+        let piece = PieceId::from_index(index);
+        if !moves.iter().any(|piece_move| piece_move.piece == piece) {
+            continue;
+        }
+
+        let without: Vec<Move> = moves.iter().filter(|piece_move| piece_move.piece != piece).cloned().collect();
+        let still_solves = match play(initial_game, &without) {
+            Some(games) => games.last().unwrap().can_exit(),
+            None => false
+        };
+
+        if still_solves {
+            moves = without;
+        }
+    }
+
+    moves
+}
+
+#[cfg(test)]
+mod tests {
+    use game::{Direction, Move, PieceId};
+    use solver::fixtures::easy_game;
+    use super::shorten;
+
+    #[test]
+    fn removes_wasted_moves() {
+        let moves = vec![
+            // Out and back again
+            Move {piece: PieceId(4), direction: Direction::Right, steps: 2},
+            Move {piece: PieceId(4), direction: Direction::Left, steps: 1},
+            Move {piece: PieceId(4), direction: Direction::Left, steps: 1},
+            // A cell at a time, with a pointless move in between
+            Move {piece: PieceId(2), direction: Direction::Down, steps: 1},
+            Move {piece: PieceId(4), direction: Direction::Right, steps: 1},
+            Move {piece: PieceId(2), direction: Direction::Down, steps: 1},
+            Move {piece: PieceId(3), direction: Direction::Down, steps: 1}
+        ];
+
+        let shortened = shorten(&easy_game(), &moves).unwrap();
+
+        assert_eq!(shortened, vec![
+            Move {piece: PieceId(2), direction: Direction::Down, steps: 2},
+            Move {piece: PieceId(3), direction: Direction::Down, steps: 1}
+        ]);
+    }
+
+    #[test]
+    fn stops_at_the_first_exit() {
+        let moves = vec![
+            Move {piece: PieceId(3), direction: Direction::Down, steps: 1},
+            Move {piece: PieceId(2), direction: Direction::Down, steps: 2},
+            Move {piece: PieceId(1), direction: Direction::Right, steps: 2}
+        ];
+
+        assert_eq!(shorten(&easy_game(), &moves).unwrap(), moves[0..2].to_vec());
+    }
+
+    #[test]
+    fn keeps_the_final_board_of_an_unfinished_game() {
+        let moves = vec![
+            Move {piece: PieceId(4), direction: Direction::Right, steps: 1},
+            Move {piece: PieceId(2), direction: Direction::Down, steps: 1},
+            Move {piece: PieceId(2), direction: Direction::Up, steps: 1},
+            Move {piece: PieceId(4), direction: Direction::Right, steps: 1}
+        ];
+
+        assert_eq!(shorten(&easy_game(), &moves).unwrap(), vec![Move {piece: PieceId(4), direction: Direction::Right, steps: 2}]);
+
+        for illegal in &[Move {piece: PieceId(1), direction: Direction::Right, steps: 1}, Move {piece: PieceId(10), direction: Direction::Down, steps: 1}] {
+            assert_eq!(shorten(&easy_game(), &[illegal.clone()]), None);
+        }
+    }
+}